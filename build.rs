@@ -13,6 +13,17 @@ use std::fs::{self, File};
 use std::io::Write;
 use std::path;
 
+// The value enums used by the command line live with the code that uses
+// them, at the same paths as in the crate
+mod report {
+    include!("src/report/output_format.rs");
+}
+mod updater {
+    pub mod github {
+        include!("src/updater/github/source.rs");
+    }
+}
+
 include!("src/cli.rs");
 
 fn generate_man_page<P: AsRef<path::Path>>(outdir: P) -> Result<()> {
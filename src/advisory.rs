@@ -0,0 +1,346 @@
+// Copyright (C) 2022 Leandro Lisboa Penz <lpenz@lpenz.org>
+// This file is subject to the terms and conditions defined in
+// file 'LICENSE', which is part of this source code package.
+
+//! Local [`Database`] of advisories for vulnerable action and image
+//! versions.
+//!
+//! Advisories use the OSV JSON format of the github advisory database,
+//! where `affected` entries with the `GitHub Actions` ecosystem refer to
+//! `owner/repo` github resources. Docker images are not covered, as there
+//! is no OSV ecosystem for them.
+
+use color_eyre::{Result, eyre::eyre};
+use serde_json::Value;
+use std::path;
+use tokio_stream::StreamExt;
+use tokio_stream::wrappers::ReadDirStream;
+use tracing::Level;
+use tracing::event;
+use tracing::instrument;
+
+use crate::resource::Resource;
+use crate::version::Version;
+
+/// A vulnerability that affects a resource-version.
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct Vulnerability {
+    /// Identifier of the advisory, usually a GHSA ID.
+    pub id: String,
+    pub summary: String,
+    /// First version that fixes the vulnerability, if there is one.
+    pub fixed: Option<Version>,
+}
+
+/// End of an interval of affected versions.
+#[derive(Debug, Clone)]
+enum End {
+    Fixed(Version),
+    LastAffected(Version),
+}
+
+#[derive(Debug, Clone)]
+struct Affected {
+    ecosystem: String,
+    name: String,
+    /// Intervals of affected versions, with the version that introduced
+    /// the vulnerability and the version that fixed it.
+    intervals: Vec<(Option<Version>, Option<End>)>,
+    /// Explicitly listed affected versions.
+    versions: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+struct Advisory {
+    id: String,
+    summary: String,
+    affected: Vec<Affected>,
+}
+
+#[derive(Debug, Default)]
+pub struct Database {
+    advisories: Vec<Advisory>,
+}
+
+impl Database {
+    /// Load all `.json` advisories under the given directory,
+    /// recursively.
+    #[instrument(level="debug", fields(dir = ?dir.as_ref().display()))]
+    pub async fn load(dir: impl AsRef<path::Path>) -> Result<Database> {
+        let mut advisories = vec![];
+        let mut dirs = vec![dir.as_ref().to_owned()];
+        while let Some(dir) = dirs.pop() {
+            let mut entries = ReadDirStream::new(tokio::fs::read_dir(&dir).await?);
+            while let Some(entry) = entries.next().await {
+                let path = entry?.path();
+                if tokio::fs::metadata(&path).await?.is_dir() {
+                    dirs.push(path);
+                } else if path.extension().is_some_and(|ext| ext == "json") {
+                    let contents = tokio::fs::read_to_string(&path).await?;
+                    let advisory = parse(&contents)
+                        .map_err(|e| eyre!("error parsing {}: {}", path.display(), e))?;
+                    advisories.push(advisory);
+                }
+            }
+        }
+        event!(
+            Level::INFO,
+            num_advisories = advisories.len(),
+            "advisories loaded"
+        );
+        Ok(Database { advisories })
+    }
+
+    /// Get the vulnerabilities that affect the given resource-version.
+    #[instrument(level = "debug", skip(self))]
+    pub fn vulnerabilities(&self, resource: &Resource, version: &Version) -> Vec<Vulnerability> {
+        let (ecosystem, name) = match resource {
+            Resource::GhAction { user, repo } | Resource::GhWorkflow { user, repo, .. } => {
+                ("GitHub Actions", format!("{user}/{repo}"))
+            }
+            Resource::Docker { .. } | Resource::Git { .. } => return vec![],
+        };
+        self.advisories
+            .iter()
+            .flat_map(|advisory| {
+                advisory
+                    .affected
+                    .iter()
+                    .filter(|affected| {
+                        affected.ecosystem == ecosystem && affected.name.eq_ignore_ascii_case(&name)
+                    })
+                    .filter_map(|affected| affected.matches(version))
+                    .map(|fixed| Vulnerability {
+                        id: advisory.id.clone(),
+                        summary: advisory.summary.clone(),
+                        fixed,
+                    })
+            })
+            .collect()
+    }
+}
+
+impl Affected {
+    /// First fixed version after the given one, or the last fixed version
+    /// if the given one is not semver.
+    fn first_fix(&self, version: &Version) -> Option<Version> {
+        let fixes = self.intervals.iter().filter_map(|(_, end)| match end {
+            Some(End::Fixed(fixed)) if fixed.version.is_some() => Some(fixed),
+            _ => None,
+        });
+        match &version.version {
+            Some(v) => fixes
+                .filter(|fixed| fixed.version.as_ref().is_some_and(|f| f > v))
+                .min(),
+            None => fixes.max(),
+        }
+        .cloned()
+    }
+
+    /// Check if the version is affected, returning the version that
+    /// fixes it.
+    fn matches(&self, version: &Version) -> Option<Option<Version>> {
+        let strip = |s: &str| s.strip_prefix('v').unwrap_or(s).to_string();
+        if self
+            .versions
+            .iter()
+            .any(|affected| strip(affected) == strip(&version.string))
+        {
+            return Some(self.first_fix(version));
+        }
+        let v = version.version.as_ref()?;
+        self.intervals.iter().find_map(|(introduced, end)| {
+            if introduced
+                .as_ref()
+                .and_then(|introduced| introduced.version.as_ref())
+                .is_some_and(|introduced| v < introduced)
+            {
+                return None;
+            }
+            match end {
+                Some(End::Fixed(fixed)) if fixed.version.as_ref().is_some_and(|f| v >= f) => None,
+                Some(End::LastAffected(last)) if last.version.as_ref().is_some_and(|l| v > l) => {
+                    None
+                }
+                Some(End::Fixed(fixed)) => Some(Some(fixed.clone())),
+                _ => Some(None),
+            }
+        })
+    }
+}
+
+fn parse(contents: &str) -> Result<Advisory> {
+    let data: Value = serde_json::from_str(contents)?;
+    let id = data
+        .get("id")
+        .and_then(|id| id.as_str())
+        .ok_or_else(|| eyre!("id not found in advisory"))?;
+    let summary = data
+        .get("summary")
+        .and_then(|summary| summary.as_str())
+        .unwrap_or_default();
+    let affected = data
+        .get("affected")
+        .and_then(|affected| affected.as_array())
+        .ok_or_else(|| eyre!("affected list not found in advisory"))?
+        .iter()
+        .map(parse_affected)
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Advisory {
+        id: id.into(),
+        summary: summary.into(),
+        affected,
+    })
+}
+
+fn parse_affected(data: &Value) -> Result<Affected> {
+    let package = data
+        .get("package")
+        .ok_or_else(|| eyre!("package not found in affected entry"))?;
+    let field = |name: &str| {
+        package
+            .get(name)
+            .and_then(|value| value.as_str())
+            .map(String::from)
+            .ok_or_else(|| eyre!("{name} not found in package"))
+    };
+    let mut intervals = vec![];
+    for range in data
+        .get("ranges")
+        .and_then(|ranges| ranges.as_array())
+        .into_iter()
+        .flatten()
+    {
+        let mut introduced = None;
+        let mut open = false;
+        for event in range
+            .get("events")
+            .and_then(|events| events.as_array())
+            .into_iter()
+            .flatten()
+        {
+            let version = |name: &str| {
+                event
+                    .get(name)
+                    .and_then(|v| v.as_str())
+                    .and_then(Version::new)
+            };
+            if let Some(v) = version("introduced") {
+                introduced = Some(v);
+                open = true;
+            } else if let Some(v) = version("fixed") {
+                intervals.push((introduced.take(), Some(End::Fixed(v))));
+                open = false;
+            } else if let Some(v) = version("last_affected") {
+                intervals.push((introduced.take(), Some(End::LastAffected(v))));
+                open = false;
+            }
+        }
+        if open {
+            intervals.push((introduced, None));
+        }
+    }
+    let versions = data
+        .get("versions")
+        .and_then(|versions| versions.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|v| v.as_str().map(String::from))
+        .collect();
+    Ok(Affected {
+        ecosystem: field("ecosystem")?,
+        name: field("name")?,
+        intervals,
+        versions,
+    })
+}
+
+#[test]
+fn test_advisory_vulnerabilities() -> Result<()> {
+    let json_str = r#"
+{
+  "id": "GHSA-mrrh-fwg8-r2c3",
+  "summary": "tj-actions changed-files through 45.0.7 allows remote attackers to discover secrets",
+  "affected": [
+    {
+      "package": {
+        "ecosystem": "GitHub Actions",
+        "name": "tj-actions/changed-files"
+      },
+      "ranges": [
+        {
+          "type": "ECOSYSTEM",
+          "events": [
+            { "introduced": "0" },
+            { "fixed": "46.0.1" }
+          ]
+        }
+      ]
+    }
+  ]
+}
+"#;
+    let db = Database {
+        advisories: vec![parse(json_str)?],
+    };
+    let resource = Resource::new_ghaction("tj-actions".into(), "changed-files".into());
+    let vulns = db.vulnerabilities(&resource, &Version::new("v45.0.7").unwrap());
+    assert_eq!(vulns.len(), 1);
+    assert_eq!(vulns[0].id, "GHSA-mrrh-fwg8-r2c3");
+    assert_eq!(vulns[0].fixed, Version::new("46.0.1"));
+    assert!(
+        db.vulnerabilities(&resource, &Version::new("v46.0.1").unwrap())
+            .is_empty()
+    );
+    let other = Resource::new_ghaction("actions".into(), "checkout".into());
+    assert!(
+        db.vulnerabilities(&other, &Version::new("v1").unwrap())
+            .is_empty()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_advisory_explicit_versions() -> Result<()> {
+    let json_str = r#"
+{
+  "id": "GHSA-0000-0000-0000",
+  "summary": "explicitly listed versions",
+  "affected": [
+    {
+      "package": { "ecosystem": "GitHub Actions", "name": "lpenz/action" },
+      "ranges": [
+        {
+          "type": "ECOSYSTEM",
+          "events": [
+            { "introduced": "0" },
+            { "fixed": "1.2.0" },
+            { "introduced": "2.0.0" },
+            { "fixed": "2.0.3" }
+          ]
+        }
+      ],
+      "versions": ["v1", "2.0.1"]
+    }
+  ]
+}
+"#;
+    let db = Database {
+        advisories: vec![parse(json_str)?],
+    };
+    let resource = Resource::new_ghaction("lpenz".into(), "action".into());
+    let fixed = |version: &str| {
+        db.vulnerabilities(&resource, &Version::new(version).unwrap())
+            .into_iter()
+            .map(|vuln| vuln.fixed)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(fixed("v1"), [Version::new("1.2.0")]);
+    assert_eq!(fixed("v2.0.1"), [Version::new("2.0.3")]);
+    let docker = Resource::new_docker("lpenz/action".into());
+    assert!(
+        db.vulnerabilities(&docker, &Version::new("1.0.0").unwrap())
+            .is_empty()
+    );
+    Ok(())
+}
@@ -0,0 +1,255 @@
+// Copyright (C) 2022 Leandro Lisboa Penz <lpenz@lpenz.org>
+// This file is subject to the terms and conditions defined in
+// file 'LICENSE', which is part of this source code package.
+
+//! Persistent [`DiskCache`] of the versions of each resource, shared by
+//! runs of the tool.
+//!
+//! Entries are used as they are until their TTL expires; they are then
+//! refreshed with conditional requests, which the server answers with
+//! 304 Not Modified if nothing changed. Those don't count against the
+//! github rate limit.
+//!
+//! The settings that change the versions we get, like the version source
+//! or the API URL, are part of the key of the entries.
+
+use color_eyre::{Result, eyre::eyre};
+use serde_json::Value;
+use std::io;
+use std::path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::Level;
+use tracing::event;
+use tracing::instrument;
+
+use crate::resource::Kind;
+use crate::resource::Resource;
+use crate::updater;
+use crate::updater::http::Validators;
+use crate::version::Version;
+use crate::version::VersionInfo;
+
+/// Versions of a resource, as fetched at some point.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub versions: Vec<VersionInfo>,
+    /// Validators of the response that the versions came from.
+    pub validators: Validators,
+    pub fetched: SystemTime,
+}
+
+impl Entry {
+    /// Create an entry fetched now.
+    pub fn new(versions: Vec<VersionInfo>, validators: Validators) -> Entry {
+        Entry {
+            versions,
+            validators,
+            fetched: SystemTime::now(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct DiskCache {
+    dir: path::PathBuf,
+    /// How long entries are used without checking the upstream.
+    ttl: Duration,
+}
+
+impl DiskCache {
+    pub fn new(dir: path::PathBuf, ttl: Duration) -> DiskCache {
+        DiskCache { dir, ttl }
+    }
+
+    /// Default cache directory, under `$XDG_CACHE_HOME` or `~/.cache`.
+    pub fn default_dir() -> Option<path::PathBuf> {
+        let cache = match std::env::var_os("XDG_CACHE_HOME") {
+            Some(cache) => path::PathBuf::from(cache),
+            None => path::PathBuf::from(std::env::var_os("HOME")?).join(".cache"),
+        };
+        Some(cache.join(env!("CARGO_PKG_NAME")))
+    }
+
+    /// File of the entry of the resource, with the characters that are
+    /// not safe in filenames percent-encoded, followed by a hash of the
+    /// settings.
+    fn filename(&self, config: &updater::Config, resource: &Resource) -> path::PathBuf {
+        let name = resource
+            .to_string()
+            .bytes()
+            .map(|b| match b {
+                b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'.' | b'-' | b'_' => {
+                    (b as char).to_string()
+                }
+                _ => format!("%{b:02X}"),
+            })
+            .collect::<String>();
+        let digest = aws_lc_rs::digest::digest(
+            &aws_lc_rs::digest::SHA256,
+            settings(config, resource).as_bytes(),
+        );
+        let hash = digest.as_ref()[..8]
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<String>();
+        self.dir.join(format!("{name}.{hash}.json"))
+    }
+
+    /// Check if the entry can be used without checking the upstream.
+    pub fn is_fresh(&self, entry: &Entry) -> bool {
+        entry.fetched.elapsed().is_ok_and(|age| age < self.ttl)
+    }
+
+    /// Read the entry of the resource, if there is a valid one.
+    #[instrument(level = "debug", skip(config))]
+    pub async fn read(&self, config: &updater::Config, resource: &Resource) -> Option<Entry> {
+        let filename = self.filename(config, resource);
+        let contents = match tokio::fs::read_to_string(&filename).await {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+            Err(e) => {
+                event!(Level::WARN, filename = ?filename, error = %e, "error reading cache entry");
+                return None;
+            }
+        };
+        match parse(&contents) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                event!(Level::WARN, filename = ?filename, error = %e, "invalid cache entry");
+                None
+            }
+        }
+    }
+
+    /// Write the entry of the resource.
+    #[instrument(level = "debug", skip(config, entry))]
+    pub async fn write(
+        &self,
+        config: &updater::Config,
+        resource: &Resource,
+        entry: &Entry,
+    ) -> Result<()> {
+        let versions = entry
+            .versions
+            .iter()
+            .map(|info| {
+                serde_json::json!({
+                    "version": info.version.string,
+                    "target": info.target,
+                })
+            })
+            .collect::<Vec<_>>();
+        let fetched = entry
+            .fetched
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let data = serde_json::json!({
+            "version": 1,
+            "resource": resource.to_string(),
+            "fetched": fetched,
+            "etag": entry.validators.etag,
+            "last_modified": entry.validators.last_modified,
+            "versions": versions,
+        });
+        tokio::fs::create_dir_all(&self.dir).await?;
+        // Write to a temporary file first, so that concurrent runs never
+        // see a partial entry
+        let filename = self.filename(config, resource);
+        let tmp = filename.with_extension(format!("{}.tmp", std::process::id()));
+        tokio::fs::write(&tmp, data.to_string()).await?;
+        tokio::fs::rename(&tmp, &filename).await?;
+        Ok(())
+    }
+}
+
+/// Settings that change the versions we get for the resource.
+fn settings(config: &updater::Config, resource: &Resource) -> String {
+    match resource.kind() {
+        Kind::Docker => config.docker.api_url.to_string(),
+        Kind::GhAction | Kind::GhWorkflow => format!(
+            "{} {:?} prereleases={}",
+            config.github.api_url(resource),
+            config.github.source(resource),
+            config.github.prereleases
+        ),
+        Kind::Git => String::new(),
+    }
+}
+
+fn parse(contents: &str) -> Result<Entry> {
+    let data: Value = serde_json::from_str(contents)?;
+    let string = |name: &str| data.get(name).and_then(|v| v.as_str()).map(String::from);
+    let fetched = data
+        .get("fetched")
+        .and_then(|fetched| fetched.as_u64())
+        .ok_or_else(|| eyre!("fetched not found in cache entry"))?;
+    let versions = data
+        .get("versions")
+        .and_then(|versions| versions.as_array())
+        .ok_or_else(|| eyre!("versions not found in cache entry"))?
+        .iter()
+        .map(|info| {
+            let version = info
+                .get("version")
+                .and_then(|version| version.as_str())
+                .and_then(Version::new)
+                .ok_or_else(|| eyre!("invalid version in cache entry"))?;
+            let target = info
+                .get("target")
+                .and_then(|target| target.as_str())
+                .map(String::from);
+            Ok(VersionInfo::new(version, target))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Entry {
+        versions,
+        validators: Validators {
+            etag: string("etag"),
+            last_modified: string("last_modified"),
+        },
+        fetched: UNIX_EPOCH + Duration::from_secs(fetched),
+    })
+}
+
+#[tokio::test]
+async fn test_disk_cache() -> Result<()> {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path().to_owned();
+    let cache = DiskCache::new(dir.clone(), Duration::from_secs(3600));
+    let mut config = updater::Config::default();
+    let checkout = Resource::new_ghaction("actions".into(), "checkout".into());
+    let filename = cache.filename(&config, &checkout);
+    assert!(
+        filename
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("actions%2Fcheckout.")
+    );
+    assert!(cache.read(&config, &checkout).await.is_none());
+    let mut entry = Entry::new(
+        vec![VersionInfo::new(
+            Version::new("v4").unwrap(),
+            Some("abc".into()),
+        )],
+        Validators {
+            etag: Some("\"etag\"".into()),
+            last_modified: None,
+        },
+    );
+    // Entries are stored with a 1s resolution
+    entry.fetched =
+        UNIX_EPOCH + Duration::from_secs(entry.fetched.duration_since(UNIX_EPOCH)?.as_secs());
+    cache.write(&config, &checkout, &entry).await?;
+    let read = cache.read(&config, &checkout).await;
+    assert_eq!(read.as_ref(), Some(&entry));
+    // Entries of other settings are not used
+    config.github.source = updater::github::VersionSource::Releases;
+    assert_ne!(cache.filename(&config, &checkout), filename);
+    assert!(cache.read(&config, &checkout).await.is_none());
+    assert!(cache.is_fresh(&entry));
+    entry.fetched -= Duration::from_secs(7200);
+    assert!(!cache.is_fresh(&entry));
+    Ok(())
+}
@@ -3,7 +3,13 @@
 // file 'LICENSE', which is part of this source code package.
 
 use clap::Parser;
+use clap::Subcommand;
 use clap::ValueEnum;
+use clap::builder::RangedU64ValueParser;
+use std::path::PathBuf;
+
+pub use crate::report::OutputFormat;
+pub use crate::updater::github::VersionSource;
 
 #[derive(Parser, Debug)]
 #[command(
@@ -15,6 +21,8 @@ available versions of all github actions and workflow dispatches used, showing
 which ones can be updated and optionally updating them automatically."
 )]
 pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
     /// Don't update the workflows, just print what would be done
     #[clap(short = 'n', long = "dry-run")]
     pub dryrun: bool,
@@ -24,14 +32,169 @@ pub struct Cli {
     /// Return error if any outdated actions are found
     #[clap(long)]
     pub error_on_outdated: bool,
+    /// Return error if the versions of any resource can't be retrieved
+    #[clap(long)]
+    pub error_on_lookup: bool,
+    /// Write a Markdown summary with a table per workflow to FILE; by
+    /// default it's appended to GITHUB_STEP_SUMMARY, when set
+    #[clap(long, value_name = "FILE")]
+    pub summary: Option<PathBuf>,
+    /// Replace docker `latest` tags with the version that has the same digest
+    #[clap(long)]
+    pub pin_latest: bool,
+    /// Report floating tags (`v3`, `v3.1`) that don't point to the newest matching release
+    #[clap(long)]
+    pub check_floating: bool,
+    /// Report SHA-pinned actions whose commit is not in any upstream branch or tag
+    #[clap(long)]
+    pub check_impostors: bool,
+    /// Report actions pinned to a branch instead of a tag or commit
+    #[clap(long)]
+    pub check_branch_pins: bool,
+    /// Record the commit or digest of each version in .github/workflows.lock
+    /// and report versions that now resolve to something different
+    #[clap(long)]
+    pub lockfile: bool,
+    /// Directory with advisories in the OSV JSON format, used to report
+    /// vulnerable versions and to update them at least to the fixed version;
+    /// only github actions and workflows are checked, docker images are not
+    #[clap(long, value_name = "DIR")]
+    pub advisories: Option<PathBuf>,
+    /// Where to get the versions of github actions and workflows from
+    #[clap(long, value_enum, value_parser)]
+    pub version_source: Option<VersionSource>,
+    /// Version source for a specific github repository, overriding --version-source
+    #[clap(long, value_name = "OWNER/REPO=SOURCE", value_parser = parse_version_source_for)]
+    pub version_source_for: Vec<(String, VersionSource)>,
+    /// Consider prereleases when getting versions from github releases
+    #[clap(long)]
+    pub include_prereleases: bool,
+    /// Base URL of the github API
+    #[clap(long, env = "GITHUB_API_URL", default_value = "https://api.github.com")]
+    pub github_api_url: String,
+    /// Base URL of the github API for the repositories of a specific owner
+    #[clap(long, value_name = "OWNER=URL", value_parser = parse_github_api_url_for)]
+    pub github_api_url_for: Vec<(String, String)>,
+    /// ID of the github app used to authenticate
+    #[clap(long, value_name = "ID")]
+    pub github_app_id: Option<String>,
+    /// ID of the installation of the github app in the organization
+    #[clap(long, value_name = "ID")]
+    pub github_app_installation: Option<String>,
+    /// File with the private key of the github app, in PEM format
+    #[clap(long, value_name = "FILE")]
+    pub github_app_key: Option<PathBuf>,
+    /// Base URL of the docker hub API
+    #[clap(
+        long,
+        value_name = "URL",
+        default_value = "https://registry.hub.docker.com"
+    )]
+    pub docker_hub_url: String,
+    /// Get the versions of github resources in batches with the GraphQL
+    /// API, which requires a token
+    #[clap(long)]
+    pub graphql: bool,
+    /// List the tags with git when getting the versions of a github
+    /// resource from the API fails
+    #[clap(long)]
+    pub git_fallback: bool,
+    /// Get the versions of the resources that match the glob PATTERN with
+    /// an external backend, given by name or path
+    #[clap(long, value_name = "PATTERN=BACKEND", value_parser = parse_backend)]
+    pub backend: Vec<(String, String)>,
+    /// User-Agent of the HTTP requests
+    #[clap(long, value_name = "STRING")]
+    pub user_agent: Option<String>,
+    /// Maximum time to establish HTTP connections
+    #[clap(long, value_name = "SECONDS", default_value_t = 10)]
+    pub connect_timeout: u64,
+    /// Maximum time waiting for data from HTTP connections
+    #[clap(long, value_name = "SECONDS", default_value_t = 30)]
+    pub read_timeout: u64,
+    /// Proxy for all HTTP requests; by default the HTTP_PROXY, HTTPS_PROXY
+    /// and NO_PROXY environment variables are used
+    #[clap(long, value_name = "URL")]
+    pub proxy: Option<String>,
+    /// File with root certificates, in PEM format, to trust in addition to
+    /// the builtin ones
+    #[clap(long, value_name = "FILE")]
+    pub ca_certs: Option<PathBuf>,
+    /// Maximum number of retries of rate limited or failed requests
+    #[clap(long, value_name = "N", default_value_t = 5)]
+    pub retries: u32,
+    /// Maximum time spent on each request, including retries
+    #[clap(long, value_name = "SECONDS", default_value_t = 300)]
+    pub retry_deadline: u64,
+    /// Cache the versions of the resources on disk, under
+    /// $XDG_CACHE_HOME/github-workflows-update
+    #[clap(long)]
+    pub cache: bool,
+    /// Cache the versions of the resources on disk, in DIR
+    #[clap(long, value_name = "DIR")]
+    pub cache_dir: Option<PathBuf>,
+    /// Time during which cached versions are used without checking them
+    #[clap(long, value_name = "SECONDS", default_value_t = 3600)]
+    pub cache_ttl: u64,
+    /// Maximum number of concurrent requests to each host
+    #[clap(short = 'j', long, value_name = "N", default_value_t = 8,
+           value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    pub jobs: usize,
+    /// Maximum number of concurrent requests of BACKEND to each host,
+    /// which don't count against --jobs
+    #[clap(long, value_name = "BACKEND=N", value_parser = parse_jobs_for)]
+    pub jobs_for: Vec<(String, usize)>,
+    /// Print statistics of the lookups and HTTP requests at the end
+    #[clap(long)]
+    pub stats: bool,
+    /// Get the versions of all resources from the SNAPSHOT file, written by
+    /// the snapshot command, without network access
+    #[clap(long, value_name = "SNAPSHOT")]
+    pub offline: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Clone, PartialEq, Eq, Debug)]
+pub enum Command {
+    /// Record the versions of all resources used by the workflows in a
+    /// snapshot file, instead of checking the workflows
+    Snapshot {
+        /// File to write the snapshot to
+        #[clap(value_name = "FILE")]
+        output: PathBuf,
+    },
+}
+
+fn parse_version_source_for(s: &str) -> Result<(String, VersionSource), String> {
+    let (repo, source) = s
+        .split_once('=')
+        .ok_or_else(|| format!("invalid OWNER/REPO=SOURCE: no `=` found in `{s}`"))?;
+    Ok((repo.to_string(), VersionSource::from_str(source, true)?))
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Default, Debug)]
-pub enum OutputFormat {
-    #[default]
-    Standard,
-    /// Generate messages as github action warnings
-    GithubWarning,
+fn parse_github_api_url_for(s: &str) -> Result<(String, String), String> {
+    let (owner, url) = s
+        .split_once('=')
+        .ok_or_else(|| format!("invalid OWNER=URL: no `=` found in `{s}`"))?;
+    Ok((owner.to_string(), url.to_string()))
+}
+
+fn parse_backend(s: &str) -> Result<(String, String), String> {
+    let (pattern, backend) = s
+        .rsplit_once('=')
+        .ok_or_else(|| format!("invalid PATTERN=BACKEND: no `=` found in `{s}`"))?;
+    Ok((pattern.to_string(), backend.to_string()))
+}
+
+fn parse_jobs_for(s: &str) -> Result<(String, usize), String> {
+    let (backend, jobs) = s
+        .rsplit_once('=')
+        .ok_or_else(|| format!("invalid BACKEND=N: no `=` found in `{s}`"))?;
+    match jobs.parse::<usize>() {
+        Ok(jobs) if jobs > 0 => Ok((backend.to_string(), jobs)),
+        _ => Err(format!(
+            "invalid BACKEND=N: `{jobs}` is not a positive number"
+        )),
+    }
 }
 
 #[cfg(test)]
@@ -46,9 +209,120 @@ mod tests {
 
     #[test]
     fn test_parse_args() {
-        let args = Cli::parse_from(["test", "-n", "-f", "github-warning", "--error-on-outdated"]);
+        let args = Cli::parse_from([
+            "test",
+            "-n",
+            "-f",
+            "github-warning",
+            "--error-on-outdated",
+            "--error-on-lookup",
+            "--pin-latest",
+            "--check-floating",
+            "--check-impostors",
+            "--check-branch-pins",
+            "--lockfile",
+            "--advisories",
+            "advisory-database",
+            "--version-source",
+            "releases",
+            "--version-source-for",
+            "actions/checkout=latest-release",
+            "--include-prereleases",
+            "--github-api-url-for",
+            "corp=https://ghes.example.com/api/v3",
+            "--docker-hub-url",
+            "https://hub.example.com",
+            "--graphql",
+            "--git-fallback",
+            "--backend",
+            "docker://artifacts.corp/*=artifacts",
+            "--user-agent",
+            "corp-bot/1.0",
+            "--connect-timeout",
+            "5",
+            "--read-timeout",
+            "15",
+            "--proxy",
+            "http://proxy.corp:3128",
+            "--ca-certs",
+            "corp-ca.pem",
+            "--retries",
+            "2",
+            "--retry-deadline",
+            "60",
+            "--cache",
+            "--cache-dir",
+            "cache",
+            "--cache-ttl",
+            "600",
+            "-j",
+            "2",
+            "--jobs-for",
+            "docker=1",
+            "--stats",
+            "--offline",
+            "snapshot.json",
+            "--summary",
+            "summary.md",
+        ]);
         assert!(args.dryrun);
         assert_eq!(args.output_format, Some(OutputFormat::GithubWarning));
         assert!(args.error_on_outdated);
+        assert!(args.error_on_lookup);
+        assert!(args.pin_latest);
+        assert!(args.check_floating);
+        assert!(args.check_impostors);
+        assert!(args.check_branch_pins);
+        assert!(args.lockfile);
+        assert_eq!(args.advisories, Some(PathBuf::from("advisory-database")));
+        assert_eq!(args.version_source, Some(VersionSource::Releases));
+        assert_eq!(
+            args.version_source_for,
+            [("actions/checkout".to_string(), VersionSource::LatestRelease)]
+        );
+        assert!(args.include_prereleases);
+        assert_eq!(
+            args.github_api_url_for,
+            [(
+                "corp".to_string(),
+                "https://ghes.example.com/api/v3".to_string()
+            )]
+        );
+        assert_eq!(args.docker_hub_url, "https://hub.example.com");
+        assert!(args.graphql);
+        assert!(args.git_fallback);
+        assert_eq!(
+            args.backend,
+            [(
+                "docker://artifacts.corp/*".to_string(),
+                "artifacts".to_string()
+            )]
+        );
+        assert_eq!(args.user_agent, Some("corp-bot/1.0".to_string()));
+        assert_eq!(args.connect_timeout, 5);
+        assert_eq!(args.read_timeout, 15);
+        assert_eq!(args.proxy, Some("http://proxy.corp:3128".to_string()));
+        assert_eq!(args.ca_certs, Some(PathBuf::from("corp-ca.pem")));
+        assert_eq!(args.retries, 2);
+        assert_eq!(args.retry_deadline, 60);
+        assert!(args.cache);
+        assert_eq!(args.cache_dir, Some(PathBuf::from("cache")));
+        assert_eq!(args.cache_ttl, 600);
+        assert_eq!(args.jobs, 2);
+        assert_eq!(args.jobs_for, [("docker".to_string(), 1)]);
+        assert!(args.stats);
+        assert_eq!(args.offline, Some(PathBuf::from("snapshot.json")));
+        assert_eq!(args.summary, Some(PathBuf::from("summary.md")));
+        assert_eq!(args.command, None);
+        let args = Cli::parse_from(["test", "--graphql", "snapshot", "snapshot.json"]);
+        assert!(args.graphql);
+        assert_eq!(
+            args.command,
+            Some(Command::Snapshot {
+                output: PathBuf::from("snapshot.json")
+            })
+        );
+        assert!(Cli::try_parse_from(["test", "--jobs", "0"]).is_err());
+        assert!(Cli::try_parse_from(["test", "--jobs-for", "docker=0"]).is_err());
     }
 }
@@ -4,63 +4,240 @@
 
 //! Command line arguments parsing and main function.
 
-use color_eyre::Result;
+use color_eyre::{Result, eyre::eyre};
 use futures::future::join_all;
+use std::collections::HashSet;
+use std::path;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
 use tokio_stream::StreamExt;
 use tokio_stream::wrappers::ReadDirStream;
 use tracing::Level;
 use tracing::event;
+use url::Url;
 
+use crate::advisory::Database;
+use crate::cache::DiskCache;
 use crate::cli::Cli;
+use crate::cli::Command;
 use crate::cli::OutputFormat;
+use crate::lockfile;
+use crate::lockfile::Lockfile;
+use crate::processor;
 use crate::proxy;
+use crate::report;
+use crate::report::Formatter;
+use crate::report::Report;
+use crate::report::markdown::Markdown;
+use crate::snapshot::Snapshot;
+use crate::updater;
+use crate::updater::auth::Credentials;
+use crate::updater::github_app::App;
+use crate::updater::plugin::PREFIX;
+use crate::updater::plugin::Plugin;
+use crate::workflow::Workflow;
 use clap::Parser;
 
+/// Write the Markdown summary to the given file, or append it to the
+/// github job summary when running in an action.
+async fn write_summary(
+    filename: Option<&path::Path>,
+    report: &Report,
+    config: &updater::Config,
+) -> Result<()> {
+    let summary = Markdown { config }.format(report);
+    if let Some(filename) = filename {
+        tokio::fs::write(filename, summary).await?;
+    } else if let Some(filename) = std::env::var_os("GITHUB_STEP_SUMMARY") {
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(filename)
+            .await?;
+        file.write_all(summary.as_bytes()).await?;
+        file.flush().await?;
+    }
+    Ok(())
+}
+
 #[tokio::main]
 pub async fn main() -> Result<()> {
     let args = Cli::parse();
     env_logger::init();
-    let proxy_server = proxy::Server::new();
-    let futures = ReadDirStream::new(tokio::fs::read_dir(".github/workflows").await?)
-        .filter_map(|filename| match filename {
-            Ok(filename) => Some(filename.path()),
-            Err(ref e) => {
-                event!(
-                    Level::ERROR,
-                    error = ?e,
-                    filename = ?filename,
-                    "error getting filename from .github/workflows"
-                );
-                None
-            }
-        })
-        .map(|f| {
-            crate::processor::process_file(
-                args.dryrun,
-                args.output_format.unwrap_or_default(),
-                &proxy_server,
-                f,
+    let options = processor::Options::from(&args);
+    let ca_certs = match &args.ca_certs {
+        Some(filename) => Some(tokio::fs::read(filename).await?),
+        None => None,
+    };
+    let user_agent = args
+        .user_agent
+        .clone()
+        .unwrap_or_else(|| updater::http::USER_AGENT.into());
+    let client = updater::http::client(&updater::http::ClientOptions {
+        user_agent: user_agent.clone(),
+        connect_timeout: Duration::from_secs(args.connect_timeout),
+        read_timeout: Duration::from_secs(args.read_timeout),
+        proxy: args.proxy.clone(),
+        ca_certs,
+    })?;
+    let api_url = Url::parse(&args.github_api_url)?;
+    let app = match (
+        &args.github_app_id,
+        &args.github_app_installation,
+        &args.github_app_key,
+    ) {
+        (Some(app_id), Some(installation), Some(key_file)) => Some(Arc::new(
+            App::from_key_file(
+                client.clone(),
+                api_url.clone(),
+                app_id.clone(),
+                installation.clone(),
+                key_file,
             )
-        })
-        .collect::<Vec<_>>()
-        .await;
-    let mut any_outdated = false;
-    for result in join_all(futures).await {
+            .await?,
+        )),
+        (None, None, None) => None,
+        _ => {
+            return Err(eyre!(
+                "--github-app-id, --github-app-installation and --github-app-key must be used together"
+            ));
+        }
+    };
+    let mut registry = updater::Registry::default();
+    for (pattern, backend) in &args.backend {
+        let plugin = Plugin::find(backend).ok_or_else(|| {
+            eyre!("backend {backend} not found, expected a path or {PREFIX}{backend} in PATH")
+        })?;
+        registry.register_pattern(pattern, Arc::new(plugin));
+    }
+    let offline = args.offline.is_some();
+    if let Some(filename) = &args.offline {
+        // The snapshot answers all lookups, and nothing else may use the
+        // network, github app tokens included
+        registry = updater::Registry::empty();
+        registry.register_pattern("*", Arc::new(Snapshot::read(filename).await?));
+    }
+    let updater_config = updater::Config {
+        client,
+        user_agent,
+        github: updater::github::Config {
+            credentials: Credentials::load(&api_url).await,
+            app: app.filter(|_| !offline),
+            api_url,
+            api_urls: args
+                .github_api_url_for
+                .iter()
+                .map(|(owner, url)| Ok((owner.to_lowercase(), Url::parse(url)?)))
+                .collect::<Result<_>>()?,
+            source: args.version_source.unwrap_or_default(),
+            sources: args.version_source_for.iter().cloned().collect(),
+            prereleases: args.include_prereleases,
+            graphql: args.graphql,
+            git_fallback: args.git_fallback,
+        },
+        docker: updater::docker::Config {
+            api_url: Url::parse(&args.docker_hub_url)?,
+        },
+        retry: updater::http::RetryPolicy {
+            retries: args.retries,
+            deadline: Duration::from_secs(args.retry_deadline),
+            ..Default::default()
+        },
+        registry,
+        stats: Default::default(),
+        offline,
+    };
+    let cache_ttl = Duration::from_secs(args.cache_ttl);
+    let disk_cache = match (&args.cache_dir, args.cache) {
+        (Some(dir), _) => Some(DiskCache::new(dir.clone(), cache_ttl)),
+        (None, true) => Some(DiskCache::new(
+            DiskCache::default_dir()
+                .ok_or_else(|| eyre!("cache directory not found, use --cache-dir"))?,
+            cache_ttl,
+        )),
+        (None, false) => None,
+    };
+    // Offline runs only depend on the snapshot
+    let disk_cache = disk_cache.filter(|_| !offline);
+    let proxy_server = proxy::Server::new(
+        updater_config,
+        proxy::Options {
+            disk_cache,
+            jobs: args.jobs,
+            backend_jobs: args.jobs_for.iter().cloned().collect(),
+        },
+    );
+    let filenames = workflow_files().await?;
+    if let Some(Command::Snapshot { output }) = &args.command {
+        let result = snapshot(&proxy_server, &filenames, output).await;
+        if args.stats {
+            eprint!("{}", proxy_server.config().stats);
+        }
+        return result;
+    }
+    let advisories = match &args.advisories {
+        Some(dir) => Database::load(dir).await?,
+        None => Default::default(),
+    };
+    let lockfile = if args.lockfile {
+        Some(Mutex::new(Lockfile::read(lockfile::LOCKFILE).await?))
+    } else {
+        None
+    };
+    let futures = filenames.iter().map(|f| {
+        processor::process_file(&options, &advisories, lockfile.as_ref(), &proxy_server, f)
+    });
+    let mut report = Report::default();
+    let mut any_failed = false;
+    for (filename, result) in filenames.iter().zip(join_all(futures).await) {
         match result {
-            Ok(true) => {
-                any_outdated = true;
+            Ok(file_report) => {
+                report.merge(file_report);
+            }
+            Err(e) => {
+                report.errors.push((filename.clone(), e.to_string()));
+                any_failed = true;
+            }
+        }
+    }
+    report.sort();
+    report.stats = Some(proxy_server.config().stats.clone());
+    let formatter = report::formatter(args.output_format.unwrap_or_default(), args.dryrun);
+    print!("{}", formatter.format(&report));
+    write_summary(args.summary.as_deref(), &report, proxy_server.config()).await?;
+    if args.stats {
+        eprint!("{}", proxy_server.config().stats);
+    }
+    if any_failed {
+        // Errors are traced by the underlying functions, we just need to
+        // report the failure to the shell
+        std::process::exit(1);
+    }
+    if let Some(lockfile) = lockfile
+        && !args.dryrun
+    {
+        lockfile
+            .into_inner()
+            .map_err(|e| eyre!("{e}"))?
+            .write(lockfile::LOCKFILE)
+            .await?;
+    }
+    if report.any_unresolved() && args.error_on_lookup {
+        match args.output_format.unwrap_or_default() {
+            OutputFormat::Standard | OutputFormat::Json | OutputFormat::Sarif => {
+                eprintln!("Unable to get the versions of some entities");
             }
-            Err(_) => {
-                // Errors are traced by the underlying functions, we
-                // just need to report the failure to the shell
-                std::process::exit(1);
+            OutputFormat::GithubWarning => {
+                println!("::error ::unable to get the versions of some entities");
             }
-            _ => {}
         }
+        std::process::exit(3);
     }
-    if any_outdated && args.error_on_outdated {
+    if report.any_outdated() && args.error_on_outdated {
         match args.output_format.unwrap_or_default() {
-            OutputFormat::Standard => {
+            OutputFormat::Standard | OutputFormat::Json | OutputFormat::Sarif => {
                 eprintln!("Found oudated entities");
             }
             OutputFormat::GithubWarning => {
@@ -71,3 +248,64 @@ pub async fn main() -> Result<()> {
     }
     Ok(())
 }
+
+/// Get the workflow files under `.github/workflows`.
+async fn workflow_files() -> Result<Vec<path::PathBuf>> {
+    Ok(
+        ReadDirStream::new(tokio::fs::read_dir(".github/workflows").await?)
+            .filter_map(|filename| match filename {
+                Ok(filename) => Some(filename.path()),
+                Err(ref e) => {
+                    event!(
+                        Level::ERROR,
+                        error = ?e,
+                        filename = ?filename,
+                        "error getting filename from .github/workflows"
+                    );
+                    None
+                }
+            })
+            .collect::<Vec<_>>()
+            .await,
+    )
+}
+
+/// Record the versions of all resources used by the workflows in the
+/// snapshot file.
+async fn snapshot(
+    proxy_server: &proxy::Server,
+    filenames: &[path::PathBuf],
+    output: &path::Path,
+) -> Result<()> {
+    let mut resources = HashSet::new();
+    for filename in filenames {
+        let workflow = Workflow::new(filename).await?;
+        resources.extend(workflow.uses.into_iter().map(|(resource, _)| resource));
+    }
+    let lookups = resources.iter().map(|resource| async move {
+        let versions = proxy_server.new_client().get_versions(resource).await;
+        (resource, versions)
+    });
+    let mut snapshot = Snapshot::default();
+    let mut failed = 0;
+    for (resource, versions) in join_all(lookups).await {
+        match versions {
+            Ok(versions) => {
+                let updater = resource
+                    .updater(proxy_server.config())
+                    .map(|updater| updater.name().to_string())
+                    .unwrap_or_default();
+                snapshot.insert(resource, &updater, versions);
+            }
+            Err(e) => {
+                eprintln!("unable to get the versions of {resource}: {e}");
+                failed += 1;
+            }
+        }
+    }
+    snapshot.write(output).await?;
+    if failed > 0 {
+        return Err(eyre!("unable to get the versions of {failed} resources"));
+    }
+    Ok(())
+}
@@ -20,8 +20,34 @@ pub enum Error {
     VersionParsing(String),
     #[error("{1} while getting {0}")]
     HttpError(url::Url, reqwest::StatusCode),
+    #[error("rate limited ({1}) while getting {0}, gave up retrying")]
+    RateLimited(url::Url, reqwest::StatusCode),
+    #[error("github GraphQL error: {0}")]
+    Graphql(String),
+    #[error("backend {0} failed: {1}")]
+    Plugin(String, String),
+    #[error("no updater for {0}")]
+    NoUpdater(String),
+    #[error("no versions found for {0}")]
+    NoVersions(String),
+    #[error("proxy server is gone")]
+    ProxyGone,
+    #[error("{0} not found in the snapshot")]
+    NotInSnapshot(String),
+    #[error("not getting {0} in offline mode")]
+    Offline(url::Url),
+    #[error("{0} is not hosted in git")]
+    NotGit(String),
+    #[error("invalid git smart-HTTP response: {0}")]
+    GitProtocol(String),
     #[error("{0} while parsing json")]
     JsonParsing(String),
+    #[error("{0} is not a github resource")]
+    NotGithub(String),
+    #[error("{0} is not in docker hub")]
+    NotDockerHub(String),
+    #[error("invalid github app private key: {0}")]
+    GithubAppKey(String),
 
     // Forwarded errors
     #[error(transparent)]
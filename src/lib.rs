@@ -13,18 +13,42 @@
 //!     [`semver`] with [`lenient_semver`]
 //! - Main functionality:
 //!   - [`processor`]: top level file processing function.
+//!   - [`report`]: the [`report::Report`] with the findings of the run,
+//!     and the formatters that render it.
 //!   - [`workflow`]: workflow file parsing, into [`workflow::Workflow`] type.
 //!     The workflow has the the set of resource-versions that the workflow
 //!     `uses`, and also fetches all latest versions using the proxy.
 //!   - [`proxy`]: a proxy [`proxy::Server`] that makes async
 //!     requests and caches the results.
+//!   - [`cache`]: the [`cache::DiskCache`] that keeps the versions of the
+//!     resources between runs.
+//!   - [`updater`]: the backends that get the versions of each kind of
+//!     resource, implementing [`updater::Updater`], and the
+//!     [`updater::Registry`] that selects them.
+//!   - [`snapshot`]: the [`snapshot::Snapshot`] of the versions of the
+//!     resources, used by offline runs.
+//!   - [`stats`]: the [`stats::Stats`] of the lookups and HTTP requests
+//!     of a run.
+//!   - [`advisory`]: local [`advisory::Database`] of vulnerable versions.
+//!   - [`lockfile`]: the [`lockfile::Lockfile`] with the commit or digest
+//!     that each resource-version resolved to.
 
+pub mod advisory;
+pub mod cache;
 pub mod cli;
 pub mod cmd;
 pub mod error;
+pub mod lockfile;
 pub mod processor;
 pub mod proxy;
+pub mod report;
 pub mod resource;
+pub mod snapshot;
+pub mod stats;
 pub mod updater;
 pub mod version;
 pub mod workflow;
+
+#[cfg(test)]
+#[path = "../tests/support/mod.rs"]
+mod support;
@@ -0,0 +1,148 @@
+// Copyright (C) 2022 Leandro Lisboa Penz <lpenz@lpenz.org>
+// This file is subject to the terms and conditions defined in
+// file 'LICENSE', which is part of this source code package.
+
+//! The [`Lockfile`] records the commit sha or image digest that each
+//! resource-version resolved to, so that we can detect tags that were
+//! moved upstream.
+
+use color_eyre::{Result, eyre::eyre};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::io;
+use std::path;
+use tracing::instrument;
+
+use crate::resource::Resource;
+use crate::version::Version;
+
+/// Default location of the lockfile, next to the workflows directory.
+pub const LOCKFILE: &str = ".github/workflows.lock";
+
+/// Resolved targets indexed by resource and then by version.
+type Entries = BTreeMap<String, BTreeMap<String, String>>;
+
+#[derive(Debug, Default)]
+pub struct Lockfile {
+    /// Entries read from the existing lockfile.
+    locked: Entries,
+    /// Entries seen in the current run, to be written back.
+    current: Entries,
+}
+
+impl Lockfile {
+    /// Read the lockfile, returning an empty one if the file doesn't
+    /// exist yet.
+    #[instrument(level="debug", fields(filename = ?filename.as_ref().display()))]
+    pub async fn read(filename: impl AsRef<path::Path>) -> Result<Lockfile> {
+        let contents = match tokio::fs::read_to_string(filename).await {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Default::default()),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Lockfile {
+            locked: parse(&contents)?,
+            current: Default::default(),
+        })
+    }
+
+    /// Write the entries recorded in the current run to the lockfile.
+    #[instrument(level="debug", fields(filename = ?filename.as_ref().display()))]
+    pub async fn write(&self, filename: impl AsRef<path::Path>) -> Result<()> {
+        let resources = self
+            .current
+            .iter()
+            .map(|(resource, versions)| {
+                let versions = versions
+                    .iter()
+                    .map(|(version, target)| (version.clone(), Value::String(target.clone())))
+                    .collect::<serde_json::Map<_, _>>();
+                (resource.clone(), Value::Object(versions))
+            })
+            .collect::<serde_json::Map<_, _>>();
+        let data = serde_json::json!({
+            "version": 1,
+            "resolved": resources,
+        });
+        let mut contents = serde_json::to_string_pretty(&data)?;
+        contents.push('\n');
+        tokio::fs::write(filename, contents).await?;
+        Ok(())
+    }
+
+    /// Return the locked target of the resource-version if it differs
+    /// from the one it currently resolves to.
+    #[instrument(level = "debug")]
+    pub fn changed(&self, resource: &Resource, version: &Version, target: &str) -> Option<String> {
+        self.locked
+            .get(&resource.to_string())
+            .and_then(|versions| versions.get(&version.string))
+            .filter(|locked| *locked != target)
+            .cloned()
+    }
+
+    /// Record the target that the resource-version resolved to, to be
+    /// written back to the lockfile.
+    ///
+    /// If the lockfile already has a target for it, we keep the locked one,
+    /// so that changes keep being reported until the entry is removed.
+    #[instrument(level = "debug")]
+    pub fn record(&mut self, resource: &Resource, version: &Version, target: &str) {
+        let resource = resource.to_string();
+        let target = self
+            .locked
+            .get(&resource)
+            .and_then(|versions| versions.get(&version.string))
+            .cloned()
+            .unwrap_or_else(|| target.into());
+        self.current
+            .entry(resource)
+            .or_default()
+            .insert(version.string.clone(), target);
+    }
+}
+
+fn parse(contents: &str) -> Result<Entries> {
+    let data: Value = serde_json::from_str(contents)?;
+    let resolved = data
+        .get("resolved")
+        .ok_or_else(|| eyre!("resolved entry not found in lockfile"))?
+        .as_object()
+        .ok_or_else(|| eyre!("invalid type for resolved entry in lockfile"))?;
+    resolved
+        .iter()
+        .map(|(resource, versions)| {
+            let versions = versions
+                .as_object()
+                .ok_or_else(|| eyre!("invalid type for {resource} entry in lockfile"))?
+                .iter()
+                .map(|(version, target)| {
+                    let target = target.as_str().ok_or_else(|| {
+                        eyre!("invalid type for {resource} {version} entry in lockfile")
+                    })?;
+                    Ok((version.clone(), target.to_string()))
+                })
+                .collect::<Result<BTreeMap<_, _>>>()?;
+            Ok((resource.clone(), versions))
+        })
+        .collect()
+}
+
+#[test]
+fn test_lockfile_record() -> Result<()> {
+    let mut lockfile = Lockfile {
+        locked: parse(r#"{"version": 1, "resolved": {"actions/checkout": {"v4": "aaa"}}}"#)?,
+        current: Default::default(),
+    };
+    let checkout = Resource::new_ghaction("actions".into(), "checkout".into());
+    let v4 = Version::new("v4").unwrap();
+    let v5 = Version::new("v5").unwrap();
+    assert_eq!(lockfile.changed(&checkout, &v4, "aaa"), None);
+    assert_eq!(lockfile.changed(&checkout, &v4, "bbb"), Some("aaa".into()));
+    assert_eq!(lockfile.changed(&checkout, &v5, "ccc"), None);
+    lockfile.record(&checkout, &v4, "bbb");
+    lockfile.record(&checkout, &v5, "ccc");
+    assert_eq!(lockfile.current["actions/checkout"]["v4"], "aaa");
+    assert_eq!(lockfile.current["actions/checkout"]["v5"], "ccc");
+    Ok(())
+}
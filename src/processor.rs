@@ -5,24 +5,89 @@
 //! Top level file processing function.
 
 use color_eyre::Result;
+use std::collections::HashSet;
 use std::path;
+use std::sync::Mutex;
 use tracing::Level;
 use tracing::event;
 use tracing::instrument;
 
-use crate::cli::OutputFormat;
+use crate::advisory;
+use crate::cli::Cli;
+use crate::lockfile::Lockfile;
 use crate::proxy;
+use crate::report::Decision;
+use crate::report::Finding;
+use crate::report::Reason;
+use crate::report::Reference;
+use crate::report::Report;
+use crate::resource::Resource;
+use crate::version::Version;
 use crate::workflow::Workflow;
 
-/// Process the provided file.
-/// Returns true if any outdated entities were found, false otherwise.
+/// Options that control how files are processed.
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    /// Don't update the workflows.
+    pub dryrun: bool,
+    /// Replace docker `latest` tags with the version that has the same
+    /// digest.
+    pub pin_latest: bool,
+    /// Report floating tags that don't point to the newest release.
+    pub check_floating: bool,
+    /// Report SHA pins that are not reachable from upstream.
+    pub check_impostors: bool,
+    /// Report versions that are upstream branches.
+    pub check_branch_pins: bool,
+}
+
+impl From<&Cli> for Options {
+    fn from(args: &Cli) -> Self {
+        Options {
+            dryrun: args.dryrun,
+            pin_latest: args.pin_latest,
+            check_floating: args.check_floating,
+            check_impostors: args.check_impostors,
+            check_branch_pins: args.check_branch_pins,
+        }
+    }
+}
+
+/// Create the finding of the resource-version used in the workflow.
+fn finding(
+    workflow: &Workflow,
+    resource: &Resource,
+    version: &Version,
+    proposed: Option<&Version>,
+    reason: Reason,
+) -> Finding {
+    Finding::new(
+        &workflow.filename,
+        workflow.location(resource, version),
+        resource,
+        version,
+        proposed,
+        reason,
+    )
+}
+
+/// Process the provided file, returning the [`Report`] with what was
+/// found.
 #[instrument(level="info", fields(filename = ?filename.as_ref().display()))]
 pub async fn process_file(
-    dryrun: bool,
-    output_format: OutputFormat,
+    options: &Options,
+    advisories: &advisory::Database,
+    lockfile: Option<&Mutex<Lockfile>>,
     proxy_server: &proxy::Server,
     filename: impl AsRef<path::Path>,
-) -> Result<bool> {
+) -> Result<Report> {
+    let Options {
+        dryrun,
+        pin_latest,
+        check_floating,
+        check_impostors,
+        check_branch_pins,
+    } = options.clone();
     let filename = filename.as_ref();
     let mut workflow = match Workflow::new(filename).await {
         Ok(entities) => entities,
@@ -35,53 +100,235 @@ pub async fn process_file(
             return Err(e);
         }
     };
-    workflow.fetch_latest_versions(proxy_server).await;
-    let dryrunmsg = if dryrun { " (dryrun)" } else { "" };
-    let mut any_outdated = false;
+    workflow
+        .fetch_latest_versions(proxy_server, pin_latest)
+        .await;
+    let mut report = Report::default();
+    for (resource, current_version) in &workflow.uses {
+        if let Some(error) = workflow.unresolved.get(resource) {
+            report.push(finding(
+                &workflow,
+                resource,
+                current_version,
+                None,
+                Reason::Unresolved(error.clone()),
+            ));
+        } else if workflow.unsupported.contains(resource) {
+            report.push(finding(
+                &workflow,
+                resource,
+                current_version,
+                None,
+                Reason::Skipped {
+                    check: "updates",
+                    why: "only docker hub images are supported",
+                },
+            ));
+        }
+    }
+    let vulnerable = workflow
+        .uses
+        .iter()
+        .flat_map(|(resource, current_version)| {
+            advisories
+                .vulnerabilities(resource, current_version)
+                .into_iter()
+                .map(|vulnerability| (resource.clone(), current_version.clone(), vulnerability))
+        })
+        .collect::<Vec<_>>();
+    for (resource, current_version, vulnerability) in &vulnerable {
+        let mut proposed = None;
+        if let Some(fixed) = &vulnerability.fixed {
+            workflow
+                .enforce_minimum(proxy_server, resource, current_version, fixed)
+                .await;
+            // Propose the upstream tag that has the fix, if there is one
+            proposed = workflow
+                .latest
+                .get(&(resource.clone(), current_version.clone()))
+                .filter(|latest| latest.version.is_some() && latest.version >= fixed.version)
+                .cloned();
+        }
+        report.push(finding(
+            &workflow,
+            resource,
+            current_version,
+            proposed.as_ref(),
+            Reason::Vulnerable(vulnerability.clone()),
+        ));
+    }
     for (resource, current_version) in &workflow.uses {
-        if let Some(latest_version) = workflow.latest.get(resource) {
+        let candidate = workflow
+            .latest
+            .get(&(resource.clone(), current_version.clone()));
+        let decision = if workflow.unresolved.contains_key(resource) {
+            Decision::Unresolved
+        } else if candidate.is_some_and(|latest_version| latest_version != current_version) {
+            Decision::Update
+        } else {
+            Decision::Keep
+        };
+        // The versions were already looked up, so this doesn't make any
+        // requests
+        let known = proxy_server
+            .new_client()
+            .get_versions(resource)
+            .await
+            .is_ok_and(|versions| versions.iter().any(|info| &info.version == current_version));
+        report.push_reference(Reference {
+            file: filename.to_owned(),
+            location: workflow.location(resource, current_version),
+            resource: resource.clone(),
+            current: current_version.clone(),
+            known,
+            candidate: candidate.cloned(),
+            decision,
+            applied: false,
+        });
+        if let Some(latest_version) = candidate {
             if current_version == latest_version {
                 continue;
             }
-            any_outdated = true;
-            match output_format {
-                OutputFormat::Standard => {
-                    println!(
-                        "{}: update {} from {} to {}{}",
-                        filename.display(),
-                        resource,
-                        current_version,
-                        latest_version,
-                        dryrunmsg
-                    );
-                }
-                OutputFormat::GithubWarning => {
-                    println!(
-                        "::warning file={}::update {} from {} to {}",
-                        filename.display(),
-                        resource,
-                        current_version,
-                        latest_version,
-                    );
-                }
+            report.push(finding(
+                &workflow,
+                resource,
+                current_version,
+                Some(latest_version),
+                Reason::Outdated,
+            ));
+        }
+    }
+    // The checks below query upstream directly, which the snapshot of
+    // offline runs can't answer
+    let offline = proxy_server.config().offline;
+    let skipped = |check| Reason::Skipped {
+        check,
+        why: "not available in offline mode",
+    };
+    if check_floating && offline {
+        for (resource, floating) in workflow.floating_tags() {
+            report.push(finding(
+                &workflow,
+                resource,
+                floating,
+                None,
+                skipped("stale floating tags"),
+            ));
+        }
+    } else if check_floating {
+        for (resource, floating, newest) in workflow.check_floating(proxy_server).await {
+            report.push(finding(
+                &workflow,
+                &resource,
+                &floating,
+                Some(&newest),
+                Reason::StaleFloating,
+            ));
+        }
+    }
+    if check_branch_pins && offline {
+        for (resource, version) in workflow.named_versions() {
+            report.push(finding(
+                &workflow,
+                resource,
+                version,
+                None,
+                skipped("branch pins"),
+            ));
+        }
+    } else if check_branch_pins {
+        for (resource, version) in workflow.check_branch_pins(proxy_server).await {
+            report.push(finding(
+                &workflow,
+                &resource,
+                &version,
+                None,
+                Reason::BranchPinned,
+            ));
+        }
+    }
+    if check_impostors && offline {
+        for (resource, version) in workflow.sha_pins() {
+            report.push(finding(
+                &workflow,
+                resource,
+                version,
+                None,
+                skipped("unreachable commits"),
+            ));
+        }
+    } else if check_impostors {
+        for (resource, version) in workflow.check_impostors(proxy_server).await {
+            report.push(finding(
+                &workflow,
+                &resource,
+                &version,
+                None,
+                Reason::Impostor,
+            ));
+        }
+    }
+    if let Some(lockfile) = lockfile {
+        // The resource-versions that the workflow uses after the update
+        let updated = workflow
+            .uses
+            .iter()
+            .map(|rv| {
+                let (resource, current_version) = rv;
+                let version = match workflow.latest.get(rv) {
+                    Some(latest_version) if !dryrun => latest_version,
+                    _ => current_version,
+                };
+                (resource.clone(), version.clone())
+            })
+            .collect::<HashSet<_>>();
+        let targets = workflow
+            .resolve_targets(proxy_server, workflow.uses.union(&updated))
+            .await;
+        let mut lockfile = lockfile.lock().unwrap();
+        for rv in &workflow.uses {
+            let (resource, version) = rv;
+            let Some(target) = targets.get(rv) else {
+                continue;
+            };
+            if let Some(locked) = lockfile.changed(resource, version, target) {
+                report.push(finding(
+                    &workflow,
+                    resource,
+                    version,
+                    None,
+                    Reason::LockChanged {
+                        target: target.clone(),
+                        locked,
+                    },
+                ));
+            }
+        }
+        for rv in &updated {
+            if let Some(target) = targets.get(rv) {
+                lockfile.record(&rv.0, &rv.1, target);
             }
         }
     }
     if !dryrun {
         match workflow.update_file().await {
-            Ok(true) => {
+            Ok(replaced) if replaced.is_empty() => {
                 event!(
                     Level::INFO,
                     filename = ?filename,
-                    "updated"
+                    "unchanged"
                 );
             }
-            Ok(false) => {
+            Ok(replaced) => {
                 event!(
                     Level::INFO,
                     filename = ?filename,
-                    "unchanged"
+                    "updated"
                 );
+                for reference in &mut report.references {
+                    reference.applied =
+                        replaced.contains(&(reference.resource.clone(), reference.current.clone()));
+                }
             }
             Err(e) => {
                 event!(
@@ -90,8 +337,12 @@ pub async fn process_file(
                     filename = ?filename,
                     "error writing updated file"
                 );
+                report.errors.push((
+                    filename.to_owned(),
+                    format!("error writing updated file: {e}"),
+                ));
             }
         }
     }
-    Ok(any_outdated)
+    Ok(report)
 }
@@ -4,19 +4,119 @@
 
 //! The proxy [`Server`] spawns a task that makes async requests and
 //! caches the result, while async [`Client`] provides the API.
+//!
+//! Failed lookups are cached as well, with the [`Lookup`] error that
+//! caused them shared by all clients.
+//!
+//! When the github GraphQL API is enabled, requests of github resources
+//! are gathered for a short while and then looked up in batches.
+//!
+//! The server can also use a [`DiskCache`], which keeps the versions
+//! between runs.
+//!
+//! Lookups are limited to a number of concurrent ones per backend and
+//! host, given by [`Options`]; the others wait for a free slot.
 
-use color_eyre::Result;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::OwnedSemaphorePermit;
+use tokio::sync::Semaphore;
 use tokio::sync::mpsc;
 use tokio::sync::oneshot;
 use tracing::{Level, event, instrument};
 
+use crate::cache::DiskCache;
+use crate::cache::Entry;
+use crate::error;
+use crate::error::Error;
 use crate::resource::Resource;
+use crate::updater;
+use crate::updater::Fetched;
 use crate::version::Version;
+use crate::version::VersionInfo;
 
 #[derive(Debug)]
 pub struct Server {
     server_ch: mpsc::Sender<Message>,
+    context: Arc<Context>,
+}
+
+#[derive(Debug)]
+pub struct Options {
+    pub disk_cache: Option<DiskCache>,
+    /// Maximum number of concurrent lookups per host.
+    pub jobs: usize,
+    /// Maximum number of concurrent lookups per host of specific
+    /// backends, by name, which don't count against `jobs`.
+    pub backend_jobs: HashMap<String, usize>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            disk_cache: None,
+            jobs: 8,
+            backend_jobs: Default::default(),
+        }
+    }
+}
+
+/// Host that a lookup counts against, along with the backend name if it
+/// has its own limit.
+type Slot = (Option<String>, String);
+
+/// What the downloader tasks use.
+#[derive(Debug)]
+struct Context {
+    config: updater::Config,
+    options: Options,
+    slots: Mutex<HashMap<Slot, Arc<Semaphore>>>,
+}
+
+impl Context {
+    fn slot(&self, resource: &Resource) -> Option<Slot> {
+        // Resources without an updater fail without any request
+        let updater = resource.updater(&self.config).ok()?;
+        let backend = Some(updater.name().to_string())
+            .filter(|name| self.options.backend_jobs.contains_key(name));
+        Some((backend, resource.host(&self.config).unwrap_or_default()))
+    }
+
+    /// Wait for a free slot for each host of the resources.
+    async fn acquire(&self, resources: &[Resource]) -> Vec<OwnedSemaphorePermit> {
+        // Slots are always taken in the same order, so that tasks that
+        // take several of them can't deadlock
+        let slots = resources
+            .iter()
+            .filter_map(|resource| self.slot(resource))
+            .collect::<BTreeSet<_>>();
+        let mut permits = vec![];
+        for slot in slots {
+            let semaphore = {
+                let mut semaphores = self.slots.lock().expect("poisoned slots");
+                let jobs = slot
+                    .0
+                    .as_ref()
+                    .and_then(|backend| self.options.backend_jobs.get(backend))
+                    .copied()
+                    .unwrap_or(self.options.jobs);
+                semaphores
+                    .entry(slot)
+                    .or_insert_with(|| Arc::new(Semaphore::new(jobs.max(1))))
+                    .clone()
+            };
+            permits.push(
+                semaphore
+                    .acquire_owned()
+                    .await
+                    .expect("semaphores are never closed"),
+            );
+        }
+        permits
+    }
 }
 
 #[derive(Debug)]
@@ -24,31 +124,46 @@ pub struct Client {
     server_ch: mpsc::Sender<Message>,
 }
 
+/// Result of the lookup of the versions of a resource.
+pub type Lookup = Result<Vec<VersionInfo>, Arc<Error>>;
+
 #[derive(Debug)]
 pub enum Message {
     Request {
         resource: Resource,
-        client_ch: oneshot::Sender<Option<Vec<Version>>>,
+        client_ch: oneshot::Sender<Lookup>,
     },
     Downloaded {
         resource: Resource,
-        versions: Option<Vec<Version>>,
+        versions: Lookup,
     },
+    /// Look up the batch of github resources gathered so far.
+    Flush,
 }
 
-type Cache = HashMap<Resource, Option<Vec<Version>>>;
-type Pending = HashMap<Resource, Vec<oneshot::Sender<Option<Vec<Version>>>>>;
+/// How long we wait for more requests before looking up a batch.
+const BATCH_DELAY: Duration = Duration::from_millis(20);
+
+type Cache = HashMap<Resource, Lookup>;
+type Pending = HashMap<Resource, Vec<oneshot::Sender<Lookup>>>;
 
 impl Server {
-    #[instrument(level = "debug")]
-    pub fn new() -> Server {
+    #[instrument(level = "debug", skip(config))]
+    pub fn new(config: updater::Config, options: Options) -> Server {
+        let context = Arc::new(Context {
+            config,
+            options,
+            slots: Default::default(),
+        });
         let (server_ch, mut queue): (mpsc::Sender<Message>, mpsc::Receiver<Message>) =
             mpsc::channel(32);
         let worker_ch = server_ch.clone();
+        let server_context = context.clone();
         tokio::spawn(async move {
             event!(Level::INFO, "Server task started");
             let mut pending: Pending = Default::default();
             let mut cache: Cache = Default::default();
+            let mut batch: Vec<Resource> = Default::default();
             while let Some(msg) = queue.recv().await {
                 match msg {
                     Message::Request {
@@ -57,14 +172,22 @@ impl Server {
                     } => {
                         Server::handle_request(
                             worker_ch.clone(),
+                            context.clone(),
                             &cache,
                             &mut pending,
+                            &mut batch,
                             resource,
                             client_ch,
                         )
                         .await
                     }
+                    Message::Flush => {
+                        Server::flush(worker_ch.clone(), context.clone(), &mut batch);
+                    }
                     Message::Downloaded { resource, versions } => {
+                        if versions.is_err() {
+                            context.config.stats.error();
+                        }
                         cache.insert(resource.clone(), versions.clone());
                         if let Some(clients) = pending.remove(&resource) {
                             event!(
@@ -74,7 +197,13 @@ impl Server {
                                 "retrieved, answering pending"
                             );
                             for client_ch in clients {
-                                client_ch.send(versions.clone()).unwrap();
+                                if client_ch.send(versions.clone()).is_err() {
+                                    event!(
+                                        Level::WARN,
+                                        resource = %resource,
+                                        "client went away"
+                                    );
+                                }
                             }
                         } else {
                             event!(
@@ -87,29 +216,109 @@ impl Server {
                 }
             }
         });
-        Server { server_ch }
+        Server {
+            server_ch,
+            context: server_context,
+        }
     }
 
-    #[instrument(level = "debug")]
+    /// Configuration of the updaters used by the server.
+    pub fn config(&self) -> &updater::Config {
+        &self.context.config
+    }
+
+    #[instrument(level = "debug", skip(context))]
     async fn handle_request(
         worker_ch: mpsc::Sender<Message>,
+        context: Arc<Context>,
         cache: &Cache,
         pending: &mut Pending,
+        batch: &mut Vec<Resource>,
         resource: Resource,
-        client_ch: oneshot::Sender<Option<Vec<Version>>>,
+        client_ch: oneshot::Sender<Lookup>,
     ) {
+        let stats = &context.config.stats;
+        stats.lookup();
         if let Some(versions) = cache.get(&resource) {
             event!(Level::INFO, resource = %resource, "cache hit");
-            Server::worker_send(worker_ch, resource, versions.clone()).await;
+            stats.cache_hit();
+            if client_ch.send(versions.clone()).is_err() {
+                event!(Level::WARN, resource = %resource, "client went away");
+            }
             return;
         }
         let e = pending.entry(resource.clone()).or_default();
-        if e.is_empty() {
+        if e.is_empty() && updater::github_graphql::batched(&context.config, &resource) {
+            event!(Level::INFO, resource = %resource, "added to batch");
+            batch.push(resource);
+            if batch.len() >= updater::github_graphql::BATCH_SIZE {
+                Server::flush(worker_ch, context, batch);
+            } else if batch.len() == 1 {
+                tokio::spawn(async move {
+                    tokio::time::sleep(BATCH_DELAY).await;
+                    if worker_ch.send(Message::Flush).await.is_err() {
+                        event!(Level::ERROR, "error sending flush to server task");
+                    }
+                });
+            }
+        } else if e.is_empty() {
             event!(Level::INFO, resource = %resource, "downloader task started");
             tokio::spawn(async move {
-                match resource.get_versions().await {
+                let permits = context.acquire(std::slice::from_ref(&resource)).await;
+                let result = Server::lookup(&context, &resource).await;
+                drop(permits);
+                if let Err(e) = &result {
+                    event!(
+                        Level::ERROR,
+                        resource = %resource,
+                        error = %e,
+                        "error in get_version"
+                    );
+                }
+                Server::worker_send(worker_ch, resource, result.map_err(Arc::new)).await;
+            });
+        } else {
+            event!(
+                Level::INFO,
+                resource = %resource,
+                "downloader task already present"
+            );
+            stats.deduplicated();
+        }
+        e.push(client_ch);
+    }
+
+    /// Spawn a task that looks up the current batch.
+    #[instrument(level = "debug", skip(context))]
+    fn flush(worker_ch: mpsc::Sender<Message>, context: Arc<Context>, batch: &mut Vec<Resource>) {
+        if batch.is_empty() {
+            return;
+        }
+        let resources = std::mem::take(batch);
+        event!(
+            Level::INFO,
+            num_resources = resources.len(),
+            "batch downloader task started"
+        );
+        tokio::spawn(async move {
+            let mut stale = vec![];
+            for resource in resources {
+                match Server::read_fresh(&context, &resource).await {
+                    Some(versions) => {
+                        Server::worker_send(worker_ch.clone(), resource, Ok(versions)).await;
+                    }
+                    None => stale.push(resource),
+                }
+            }
+            let permits = context.acquire(&stale).await;
+            let results = updater::github_graphql::get_versions(&context.config, stale).await;
+            drop(permits);
+            for (resource, result) in results {
+                let versions = match result {
                     Ok(versions) => {
-                        Server::worker_send(worker_ch, resource, Some(versions)).await;
+                        let entry = Entry::new(versions, Default::default());
+                        Server::store(&context, &resource, &entry).await;
+                        Ok(entry.versions)
                     }
                     Err(e) => {
                         event!(
@@ -118,26 +327,84 @@ impl Server {
                             error = %e,
                             "error in get_version"
                         );
-                        Server::worker_send(worker_ch, resource, None).await;
+                        Err(Arc::new(e))
                     }
                 };
-            });
-        } else {
+                Server::worker_send(worker_ch.clone(), resource, versions).await;
+            }
+        });
+    }
+
+    /// Get the versions of the resource from the disk cache, if they are
+    /// fresh.
+    async fn read_fresh(context: &Context, resource: &Resource) -> Option<Vec<VersionInfo>> {
+        let disk_cache = context.options.disk_cache.as_ref()?;
+        let entry = disk_cache.read(&context.config, resource).await?;
+        if !disk_cache.is_fresh(&entry) {
+            return None;
+        }
+        event!(Level::INFO, resource = %resource, "disk cache hit");
+        context.config.stats.disk_cache_hit();
+        Some(entry.versions)
+    }
+
+    /// Store the versions of the resource in the disk cache, if we have
+    /// one.
+    async fn store(context: &Context, resource: &Resource, entry: &Entry) {
+        let Some(disk_cache) = &context.options.disk_cache else {
+            return;
+        };
+        if let Err(e) = disk_cache.write(&context.config, resource, entry).await {
             event!(
-                Level::INFO,
+                Level::WARN,
                 resource = %resource,
-                "downloader task already present"
+                error = %e,
+                "error writing cache entry"
             );
         }
-        e.push(client_ch);
+    }
+
+    /// Get the versions of the resource, going through the disk cache.
+    ///
+    /// Stale entries are revalidated with a conditional request, and kept
+    /// if the upstream didn't change.
+    #[instrument(level = "debug", skip(context))]
+    async fn lookup(context: &Context, resource: &Resource) -> error::Result<Vec<VersionInfo>> {
+        let Some(disk_cache) = &context.options.disk_cache else {
+            return resource.get_versions(&context.config).await;
+        };
+        let entry = disk_cache.read(&context.config, resource).await;
+        if let Some(entry) = &entry
+            && disk_cache.is_fresh(entry)
+        {
+            event!(Level::INFO, resource = %resource, "disk cache hit");
+            context.config.stats.disk_cache_hit();
+            return Ok(entry.versions.clone());
+        }
+        let validators = entry
+            .as_ref()
+            .map(|entry| entry.validators.clone())
+            .unwrap_or_default();
+        let entry = match resource
+            .get_versions_if_modified(&context.config, &validators)
+            .await?
+        {
+            Fetched::Modified(versions, validators) => Entry::new(versions, validators),
+            // Requests are only conditional when we have an entry
+            Fetched::NotModified => {
+                event!(Level::INFO, resource = %resource, "disk cache revalidated");
+                Entry::new(
+                    entry.map(|entry| entry.versions).unwrap_or_default(),
+                    validators,
+                )
+            }
+        };
+        Server::store(context, resource, &entry).await;
+        Ok(entry.versions)
     }
 
     #[instrument(level = "debug")]
-    async fn worker_send(
-        worker_ch: mpsc::Sender<Message>,
-        resource: Resource,
-        versions: Option<Vec<Version>>,
-    ) {
+    async fn worker_send(worker_ch: mpsc::Sender<Message>, resource: Resource, versions: Lookup) {
         if let Err(e) = worker_ch
             .send(Message::Downloaded { resource, versions })
             .await
@@ -160,50 +427,40 @@ impl Server {
 
 impl Default for Server {
     fn default() -> Self {
-        Self::new()
+        Self::new(Default::default(), Default::default())
     }
 }
 
 impl Client {
     #[instrument(level = "debug")]
-    pub async fn get_versions(&self, resource: &Resource) -> Result<Option<Vec<Version>>> {
+    pub async fn get_versions(&self, resource: &Resource) -> Lookup {
         let (client_ch, response) = oneshot::channel();
         self.server_ch
             .send(Message::Request {
                 resource: resource.clone(),
                 client_ch,
             })
-            .await?;
-        Ok(response.await?)
+            .await
+            .map_err(|_| Error::ProxyGone)?;
+        response.await.map_err(|_| Error::ProxyGone)?
     }
 
+    /// Get the latest version of the resource.
+    ///
+    /// If `pin_latest` is set and the current version of a docker
+    /// resource is `latest`, we return the explicit version that has the
+    /// same digest instead; we return `None` if there is no such version.
     #[instrument(level = "debug")]
     pub async fn fetch_latest_version(
         &self,
         resource: &Resource,
         current_version: &Version,
-    ) -> Option<(Resource, Version)> {
-        let versions = match self.get_versions(resource).await {
-            Ok(versions) => versions.unwrap_or_default(),
-            Err(e) => {
-                event!(
-                    Level::ERROR,
-                    resource = %resource,
-                    error = %e,
-                    "error getting version",
-                );
-                return None;
-            }
-        };
+        pin_latest: bool,
+    ) -> Result<Option<Version>, Arc<Error>> {
+        let versions = self.get_versions(resource).await?;
         if versions.is_empty() {
-            event!(
-                Level::ERROR,
-                resource = %resource,
-                versions = ?versions,
-                "no version found",
-            );
-            return None;
-        } else if !versions.contains(current_version) {
+            return Err(Arc::new(Error::NoVersions(resource.to_string())));
+        } else if !versions.iter().any(|info| &info.version == current_version) {
             event!(
                 Level::WARN,
                 resource = %resource,
@@ -212,7 +469,25 @@ impl Client {
                 "current version not present in version list",
             );
         }
-        let latest = versions.iter().max().unwrap();
+        if pin_latest && resource.is_docker() && current_version.string == "latest" {
+            let Some(pinned) = updater::docker::pin_floating(&versions, current_version) else {
+                event!(
+                    Level::WARN,
+                    resource = %resource,
+                    versions = ?versions,
+                    "no explicit version with the same digest as latest",
+                );
+                return Ok(None);
+            };
+            event!(
+                Level::INFO,
+                resource = %resource,
+                pinned = %pinned,
+                "pinned latest",
+            );
+            return Ok(Some(pinned));
+        }
+        let latest = versions.iter().map(|info| &info.version).max().unwrap();
         event!(
             Level::INFO,
             resource = %resource,
@@ -220,6 +495,154 @@ impl Client {
             latest = %latest,
             "got versions",
         );
-        Some((resource.clone(), latest.clone()))
+        Ok(Some(latest.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+
+    /// Updater that keeps track of the number of concurrent lookups.
+    #[derive(Debug, Default)]
+    struct Counting {
+        current: AtomicUsize,
+        max: AtomicUsize,
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl updater::Updater for Counting {
+        fn name(&self) -> &str {
+            "counting"
+        }
+
+        async fn get_versions(
+            &self,
+            _config: &updater::Config,
+            _resource: &Resource,
+        ) -> error::Result<Vec<VersionInfo>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            let current = self.current.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max.fetch_max(current, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            self.current.fetch_sub(1, Ordering::SeqCst);
+            Ok(vec![VersionInfo::new(Version::new("v1").unwrap(), None)])
+        }
+    }
+
+    /// Updater of resources that are gone.
+    #[derive(Debug)]
+    struct Gone;
+
+    #[async_trait]
+    impl updater::Updater for Gone {
+        fn name(&self) -> &str {
+            "gone"
+        }
+
+        async fn get_versions(
+            &self,
+            config: &updater::Config,
+            resource: &Resource,
+        ) -> error::Result<Vec<VersionInfo>> {
+            Err(Error::HttpError(
+                resource.url(config)?,
+                reqwest::StatusCode::NOT_FOUND,
+            ))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_proxy_errors() -> error::Result<()> {
+        let mut config = updater::Config::default();
+        config
+            .registry
+            .register_pattern("docker://*", Arc::new(Gone));
+        let server = Server::new(config, Default::default());
+        let resource = Resource::new_docker("gone".into());
+        let latest = Version::new("latest").unwrap();
+        // The error is cached and shared by all clients
+        for _ in 0..2 {
+            let error = server
+                .new_client()
+                .fetch_latest_version(&resource, &latest, false)
+                .await
+                .unwrap_err();
+            assert!(matches!(
+                *error,
+                Error::HttpError(_, reqwest::StatusCode::NOT_FOUND)
+            ));
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_proxy_jobs() -> error::Result<()> {
+        let counting = Arc::new(Counting::default());
+        let mut config = updater::Config::default();
+        config
+            .registry
+            .register_pattern("docker://*", counting.clone());
+        let server = Server::new(
+            config,
+            Options {
+                backend_jobs: [("counting".to_string(), 2)].into(),
+                ..Default::default()
+            },
+        );
+        // Each resource is requested twice, and looked up once
+        let requests = (0..20).map(|i| {
+            let client = server.new_client();
+            async move {
+                let resource = Resource::new_docker(format!("image{}", i % 10));
+                client.get_versions(&resource).await
+            }
+        });
+        for versions in futures::future::join_all(requests).await {
+            assert_eq!(versions.map(|versions| versions.len()).ok(), Some(1));
+        }
+        assert_eq!(counting.calls.load(Ordering::SeqCst), 10);
+        assert_eq!(counting.max.load(Ordering::SeqCst), 2);
+        let stats = server.config().stats.to_json();
+        assert_eq!(stats["lookups"], 20);
+        assert_eq!(
+            stats["deduplicated"].as_u64().unwrap() + stats["cache_hits"].as_u64().unwrap(),
+            10
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_proxy_slots() {
+        let mut config = updater::Config::default();
+        config
+            .registry
+            .register_pattern("docker://gone*", Arc::new(Gone));
+        config
+            .registry
+            .register_pattern("docker://*", Arc::new(Counting::default()));
+        let slot = |server: &Server, name: &str| {
+            server
+                .context
+                .slot(&Resource::new_docker(name.into()))
+                .unwrap()
+        };
+        // Backends share the limit of the host
+        let server = Server::new(config.clone(), Default::default());
+        assert_eq!(slot(&server, "gone"), slot(&server, "image"));
+        // Unless they have their own
+        let server = Server::new(
+            config,
+            Options {
+                backend_jobs: [("counting".to_string(), 2)].into(),
+                ..Default::default()
+            },
+        );
+        assert_eq!(slot(&server, "gone").0, None);
+        assert_eq!(slot(&server, "image").0, Some("counting".to_string()));
     }
 }
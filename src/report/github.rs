@@ -0,0 +1,96 @@
+// Copyright (C) 2022 Leandro Lisboa Penz <lpenz@lpenz.org>
+// This file is subject to the terms and conditions defined in
+// file 'LICENSE', which is part of this source code package.
+
+//! Github actions workflow commands, which show findings as annotations
+//! of the lines they refer to.
+
+use crate::report::Formatter;
+use crate::report::Report;
+
+#[derive(Debug, Default)]
+pub struct Github;
+
+/// Escape the data of a workflow command, so that it can't end the
+/// command or start another one.
+fn escape_data(data: &str) -> String {
+    data.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escape the value of a workflow command property, which can't have the
+/// separators of the properties either.
+fn escape_property(value: &str) -> String {
+    escape_data(value).replace(':', "%3A").replace(',', "%2C")
+}
+
+impl Formatter for Github {
+    fn format(&self, report: &Report) -> String {
+        report
+            .findings
+            .iter()
+            .map(|finding| {
+                let line = finding
+                    .location
+                    .map(|location| format!(",line={}", location.line))
+                    .unwrap_or_default();
+                format!(
+                    "::{} file={}{},title={}::{}\n",
+                    finding.severity,
+                    escape_property(&finding.file.display().to_string()),
+                    line,
+                    escape_property(finding.reason.id()),
+                    escape_data(&finding.message())
+                )
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn test_github() {
+    use std::path;
+
+    use crate::report::Finding;
+    use crate::report::Location;
+    use crate::report::Reason;
+    use crate::resource::Resource;
+    use crate::version::Version;
+
+    let checkout = Resource::new_ghaction("actions".into(), "checkout".into());
+    let v3 = Version::new("v3").unwrap();
+    let v4 = Version::new("v4").unwrap();
+    let mut report = Report::default();
+    report.push(Finding::new(
+        path::Path::new("a,b:100%.yml"),
+        Some(Location {
+            line: 8,
+            column: 15,
+        }),
+        &checkout,
+        &v3,
+        Some(&v4),
+        Reason::Outdated,
+    ));
+    report.push(Finding::new(
+        path::Path::new("ci.yml"),
+        None,
+        &checkout,
+        &Version::new("v1\n::error::injected").unwrap(),
+        None,
+        Reason::Skipped {
+            check: "branch pins",
+            why: "100% offline",
+        },
+    ));
+    report.sort();
+    assert_eq!(
+        Github.format(&report),
+        "::warning file=a%2Cb%3A100%25.yml,line=8,title=outdated::\
+         update actions/checkout from v3 to v4\n\
+         ::warning file=ci.yml,title=skipped::\
+         actions/checkout@v1%0A::error::injected was not checked for branch pins: \
+         100%25 offline\n"
+    );
+}
@@ -0,0 +1,186 @@
+// Copyright (C) 2022 Leandro Lisboa Penz <lpenz@lpenz.org>
+// This file is subject to the terms and conditions defined in
+// file 'LICENSE', which is part of this source code package.
+
+//! JSON output, a single document with all the resources found.
+//!
+//! The document has the following entries:
+//! - `version`: version of the schema, currently `1`; entries may be added
+//!   without changing it, but not removed or changed.
+//! - `generator`: name and version of the program.
+//! - `references`: the resource-versions used by the workflows, sorted by
+//!   file, line and resource, each with:
+//!   - `file`, `line` and `column`: where the resource-version is used;
+//!     `line` and `column` are 1-based and `null` if not found.
+//!   - `kind`: one of `docker`, `action`, `workflow` or `git`.
+//!   - `resource` and `current`: the resource and the version used.
+//!   - `known`: if `current` is one of the upstream versions.
+//!   - `candidate`: the latest version, `null` if it couldn't be looked up.
+//!   - `decision`: `keep`, `update` or `unresolved`.
+//!   - `applied`: if the update was written to the file.
+//!   - `error`: why the versions couldn't be looked up, or `null`.
+//!   - `findings`: the findings of the resource-version, each with `reason`
+//!     (`unresolved`, `vulnerable`, `outdated`, `branch-pinned`,
+//!     `stale-floating`, `impostor`, `lock-changed` or `skipped`),
+//!     `severity` (`warning` or `error`), `proposed` (version to update
+//!     to, or `null`) and `message`.
+//! - `errors`: the files that couldn't be processed, with `file` and
+//!   `error`.
+//! - `stats`: the statistics of the run, or `null`.
+
+use serde_json::Value;
+use serde_json::json;
+
+use crate::report::Finding;
+use crate::report::Formatter;
+use crate::report::Reason;
+use crate::report::Reference;
+use crate::report::Report;
+use crate::resource::Kind;
+use crate::updater;
+
+/// Version of the schema of the document.
+pub const SCHEMA_VERSION: u64 = 1;
+
+#[derive(Debug, Default)]
+pub struct Json;
+
+fn finding_json(finding: &Finding) -> Value {
+    json!({
+        "reason": finding.reason.id(),
+        "severity": finding.severity.to_string(),
+        "proposed": finding.proposed.as_ref().map(|proposed| proposed.to_string()),
+        "message": finding.message(),
+    })
+}
+
+impl Json {
+    fn reference_json(&self, report: &Report, reference: &Reference) -> Value {
+        let kind = match reference.resource.kind() {
+            Kind::Docker => "docker",
+            Kind::GhAction => "action",
+            Kind::GhWorkflow => "workflow",
+            Kind::Git => "git",
+        };
+        let findings = report.findings_of(reference).collect::<Vec<_>>();
+        let error = findings.iter().find_map(|finding| match &finding.reason {
+            Reason::Unresolved(error) => Some(error.to_string()),
+            _ => None,
+        });
+        json!({
+            "file": reference.file.display().to_string(),
+            "line": reference.location.map(|location| location.line),
+            "column": reference.location.map(|location| location.column),
+            "kind": kind,
+            "resource": reference.resource.to_string(),
+            "current": reference.current.to_string(),
+            "known": reference.known,
+            "candidate": reference.candidate.as_ref().map(|candidate| candidate.to_string()),
+            "decision": reference.decision.to_string(),
+            "applied": reference.applied,
+            "error": error,
+            "findings": findings.into_iter().map(finding_json).collect::<Vec<_>>(),
+        })
+    }
+}
+
+impl Formatter for Json {
+    fn format(&self, report: &Report) -> String {
+        let references = report
+            .references
+            .iter()
+            .map(|reference| self.reference_json(report, reference))
+            .collect::<Vec<_>>();
+        let errors = report
+            .errors
+            .iter()
+            .map(|(file, error)| {
+                json!({
+                    "file": file.display().to_string(),
+                    "error": error,
+                })
+            })
+            .collect::<Vec<_>>();
+        let data = json!({
+            "version": SCHEMA_VERSION,
+            "generator": updater::http::USER_AGENT,
+            "references": references,
+            "errors": errors,
+            "stats": report.stats.as_ref().map(|stats| stats.to_json()),
+        });
+        let mut contents =
+            serde_json::to_string_pretty(&data).expect("serializing a Value can't fail");
+        contents.push('\n');
+        contents
+    }
+}
+
+#[test]
+fn test_json() {
+    use std::path;
+    use std::sync::Arc;
+
+    use crate::error::Error;
+    use crate::report::Decision;
+    use crate::resource::Resource;
+    use crate::version::Version;
+
+    let file = path::Path::new("ci.yml");
+    let checkout = Resource::new_ghaction("actions".into(), "checkout".into());
+    let debian = Resource::new_docker("debian".into());
+    let v3 = Version::new("v3").unwrap();
+    let v4 = Version::new("v4").unwrap();
+    let v11 = Version::new("11").unwrap();
+    let mut report = Report::default();
+    report.push_reference(Reference {
+        file: file.into(),
+        location: None,
+        resource: checkout.clone(),
+        current: v3.clone(),
+        known: true,
+        candidate: Some(v4.clone()),
+        decision: Decision::Update,
+        applied: false,
+    });
+    report.push(Finding::new(
+        file,
+        None,
+        &checkout,
+        &v3,
+        Some(&v4),
+        Reason::Outdated,
+    ));
+    report.push_reference(Reference {
+        file: file.into(),
+        location: None,
+        resource: debian.clone(),
+        current: v11.clone(),
+        known: false,
+        candidate: None,
+        decision: Decision::Unresolved,
+        applied: false,
+    });
+    report.push(Finding::new(
+        file,
+        None,
+        &debian,
+        &v11,
+        None,
+        Reason::Unresolved(Arc::new(Error::ProxyGone)),
+    ));
+    report.sort();
+    let data: Value = serde_json::from_str(&Json.format(&report)).unwrap();
+    assert_eq!(data["version"], SCHEMA_VERSION);
+    assert_eq!(data["stats"], Value::Null);
+    let references = data["references"].as_array().unwrap();
+    assert_eq!(references[0]["resource"], "actions/checkout");
+    assert_eq!(references[0]["known"], true);
+    assert_eq!(references[0]["candidate"], "v4");
+    assert_eq!(references[0]["decision"], "update");
+    assert_eq!(references[0]["applied"], false);
+    assert_eq!(references[0]["findings"][0]["reason"], "outdated");
+    assert_eq!(references[1]["kind"], "docker");
+    assert_eq!(references[1]["known"], false);
+    assert_eq!(references[1]["decision"], "unresolved");
+    assert_eq!(references[1]["error"], Error::ProxyGone.to_string());
+}
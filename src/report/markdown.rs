@@ -0,0 +1,169 @@
+// Copyright (C) 2022 Leandro Lisboa Penz <lpenz@lpenz.org>
+// This file is subject to the terms and conditions defined in
+// file 'LICENSE', which is part of this source code package.
+
+//! Markdown summary, with a table per workflow file, meant for the
+//! github job summary.
+
+use std::fmt::Write;
+
+use crate::report::Decision;
+use crate::report::Formatter;
+use crate::report::Reference;
+use crate::report::Report;
+use crate::updater;
+use crate::version::Version;
+
+#[derive(Debug)]
+pub struct Markdown<'a> {
+    /// Used to get the links of the releases.
+    pub config: &'a updater::Config,
+}
+
+/// Semver component that changes in the update.
+fn update_type(current: &Version, candidate: &Version) -> &'static str {
+    match (&current.version, &candidate.version) {
+        _ if current == candidate => "none",
+        (Some(current), Some(candidate)) if current.major != candidate.major => "major",
+        (Some(current), Some(candidate)) if current.minor != candidate.minor => "minor",
+        (Some(current), Some(candidate)) if current.patch != candidate.patch => "patch",
+        _ => "other",
+    }
+}
+
+impl Markdown<'_> {
+    /// Version linked to its release page, when there is one.
+    fn link(&self, reference: &Reference, version: &Version) -> String {
+        match reference.resource.release_url(self.config, version) {
+            Some(url) => format!("[{version}]({url})"),
+            None => version.to_string(),
+        }
+    }
+
+    fn row(&self, reference: &Reference) -> String {
+        let (latest, update) = match (&reference.candidate, reference.decision) {
+            (_, Decision::Unresolved) | (None, _) => ("unresolved".to_string(), "-"),
+            (Some(candidate), _) => (
+                self.link(reference, candidate),
+                update_type(&reference.current, candidate),
+            ),
+        };
+        // Versions that are not upstream, like branches, have no page
+        let current = if reference.known {
+            self.link(reference, &reference.current)
+        } else {
+            reference.current.to_string()
+        };
+        format!(
+            "| `{}` | {} | {} | {} |\n",
+            reference.resource, current, latest, update
+        )
+    }
+}
+
+impl Formatter for Markdown<'_> {
+    fn format(&self, report: &Report) -> String {
+        let mut contents = String::from("## Workflow updates\n");
+        if report.references.is_empty() {
+            contents.push_str("\nNo resources found.\n");
+        }
+        let mut file = None;
+        for reference in &report.references {
+            if file != Some(&reference.file) {
+                file = Some(&reference.file);
+                let _ = write!(
+                    contents,
+                    "\n### `{}`\n\n| Resource | Current | Latest | Update |\n|---|---|---|---|\n",
+                    reference.file.display()
+                );
+            }
+            contents.push_str(&self.row(reference));
+        }
+        for (file, error) in &report.errors {
+            let _ = write!(contents, "\n### `{}`\n\n{}\n", file.display(), error);
+        }
+        contents
+    }
+}
+
+#[test]
+fn test_markdown() {
+    use std::path;
+
+    use crate::resource::Resource;
+
+    let config = updater::Config::default();
+    let file = path::Path::new(".github/workflows/ci.yml");
+    let v = |s: &str| Version::new(s).unwrap();
+    let mut report = Report::default();
+    report.push_reference(Reference {
+        file: file.into(),
+        location: None,
+        resource: Resource::new_ghaction("actions".into(), "checkout".into()),
+        current: v("v3.6.0"),
+        known: true,
+        candidate: Some(v("v4.1.1")),
+        decision: Decision::Update,
+        applied: false,
+    });
+    report.push_reference(Reference {
+        file: file.into(),
+        location: None,
+        resource: Resource::new_ghaction("lpenz".into(), "ghaction-cmake".into()),
+        current: v("v0.9"),
+        known: true,
+        candidate: Some(v("v0.9")),
+        decision: Decision::Keep,
+        applied: false,
+    });
+    report.push_reference(Reference {
+        file: file.into(),
+        location: None,
+        resource: Resource::new_ghaction("lpenz".into(), "ghworkflow-rust".into()),
+        current: v("main"),
+        known: false,
+        candidate: Some(v("v0.4.0")),
+        decision: Decision::Update,
+        applied: false,
+    });
+    report.push_reference(Reference {
+        file: file.into(),
+        location: None,
+        resource: Resource::new_docker("ghcr.io/lpenz/omnilint".into()),
+        current: v("0.5.1"),
+        known: true,
+        candidate: Some(v("0.5.2")),
+        decision: Decision::Update,
+        applied: false,
+    });
+    report.push_reference(Reference {
+        file: file.into(),
+        location: None,
+        resource: Resource::new_docker("debian".into()),
+        current: v("11"),
+        known: false,
+        candidate: None,
+        decision: Decision::Unresolved,
+        applied: false,
+    });
+    report.sort();
+    assert_eq!(
+        Markdown { config: &config }.format(&report),
+        "## Workflow updates\n\
+         \n\
+         ### `.github/workflows/ci.yml`\n\
+         \n\
+         | Resource | Current | Latest | Update |\n\
+         |---|---|---|---|\n\
+         | `actions/checkout` | [v3.6.0](https://github.com/actions/checkout/releases/tag/v3.6.0) \
+         | [v4.1.1](https://github.com/actions/checkout/releases/tag/v4.1.1) | major |\n\
+         | `docker://debian` | 11 | unresolved | - |\n\
+         | `docker://ghcr.io/lpenz/omnilint` | 0.5.1 | 0.5.2 | patch |\n\
+         | `lpenz/ghaction-cmake` | v0.9 | v0.9 | none |\n\
+         | `lpenz/ghworkflow-rust` | main \
+         | [v0.4.0](https://github.com/lpenz/ghworkflow-rust/releases/tag/v0.4.0) | other |\n"
+    );
+    assert_eq!(update_type(&v("v4.1.0"), &v("v4.2.0")), "minor");
+    assert_eq!(update_type(&v("v4.1.0"), &v("v4.1.1")), "patch");
+    assert_eq!(update_type(&v("main"), &v("v4")), "other");
+}
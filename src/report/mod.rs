@@ -0,0 +1,398 @@
+// Copyright (C) 2022 Leandro Lisboa Penz <lpenz@lpenz.org>
+// This file is subject to the terms and conditions defined in
+// file 'LICENSE', which is part of this source code package.
+
+//! The [`Report`] with the [`Finding`]s of a run, and the [`Formatter`]s
+//! that render it.
+//!
+//! Findings are collected while the workflows are processed and only
+//! rendered at the end, sorted by file, line and resource, so that the
+//! output doesn't depend on the order in which lookups finish.
+
+use std::fmt;
+use std::path;
+use std::sync::Arc;
+
+use crate::advisory::Vulnerability;
+use crate::error::Error;
+use crate::resource::Resource;
+use crate::stats::Stats;
+use crate::version::Version;
+
+pub mod github;
+pub mod json;
+pub mod markdown;
+mod output_format;
+pub mod sarif;
+pub mod standard;
+
+pub use output_format::OutputFormat;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// Why a resource-version was reported.
+#[derive(Debug, Clone)]
+pub enum Reason {
+    /// The versions of the resource couldn't be looked up.
+    Unresolved(Arc<Error>),
+    /// The version has a known vulnerability.
+    Vulnerable(Vulnerability),
+    /// There is a newer version.
+    Outdated,
+    /// The version is a branch instead of a tag or commit.
+    BranchPinned,
+    /// The floating tag doesn't point to the newest matching release.
+    StaleFloating,
+    /// The pinned commit is not reachable from upstream.
+    Impostor,
+    /// The version resolves to something else than what was locked.
+    LockChanged { target: String, locked: String },
+    /// A check of the version couldn't be done, for the given reason.
+    Skipped {
+        check: &'static str,
+        why: &'static str,
+    },
+}
+
+impl Reason {
+    /// Stable identifier of the kind of reason.
+    pub fn id(&self) -> &'static str {
+        match self {
+            Reason::Unresolved(_) => "unresolved",
+            Reason::Vulnerable(_) => "vulnerable",
+            Reason::Outdated => "outdated",
+            Reason::BranchPinned => "branch-pinned",
+            Reason::StaleFloating => "stale-floating",
+            Reason::Impostor => "impostor",
+            Reason::LockChanged { .. } => "lock-changed",
+            Reason::Skipped { .. } => "skipped",
+        }
+    }
+
+    /// Position of the reason among the findings of the same resource.
+    fn rank(&self) -> usize {
+        match self {
+            Reason::Unresolved(_) => 0,
+            Reason::Vulnerable(_) => 1,
+            Reason::Outdated => 2,
+            Reason::BranchPinned => 3,
+            Reason::StaleFloating => 4,
+            Reason::Impostor => 5,
+            Reason::LockChanged { .. } => 6,
+            Reason::Skipped { .. } => 7,
+        }
+    }
+}
+
+/// Position of a resource-version in its file, 1-based.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Location {
+    /// Location of the byte offset in the contents.
+    pub fn at(contents: &str, offset: usize) -> Location {
+        let before = &contents[..offset];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        Location {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub file: path::PathBuf,
+    pub location: Option<Location>,
+    pub resource: Resource,
+    pub current: Version,
+    /// Version that the resource should be updated to, if any.
+    pub proposed: Option<Version>,
+    pub reason: Reason,
+    pub severity: Severity,
+}
+
+impl Finding {
+    pub fn new(
+        file: &path::Path,
+        location: Option<Location>,
+        resource: &Resource,
+        current: &Version,
+        proposed: Option<&Version>,
+        reason: Reason,
+    ) -> Finding {
+        let severity = match reason {
+            Reason::Unresolved(_) | Reason::Vulnerable(_) | Reason::Impostor => Severity::Error,
+            Reason::Outdated
+            | Reason::BranchPinned
+            | Reason::StaleFloating
+            | Reason::LockChanged { .. }
+            | Reason::Skipped { .. } => Severity::Warning,
+        };
+        Finding {
+            file: file.to_owned(),
+            location,
+            resource: resource.clone(),
+            current: current.clone(),
+            proposed: proposed.cloned(),
+            reason,
+            severity,
+        }
+    }
+
+    /// Description of the finding, without the file.
+    pub fn message(&self) -> String {
+        let versioned = self.resource.versioned_string(&self.current);
+        let proposed = self
+            .proposed
+            .as_ref()
+            .map(|proposed| proposed.to_string())
+            .unwrap_or_default();
+        match &self.reason {
+            Reason::Unresolved(error) => {
+                format!("unable to get the versions of {}: {}", self.resource, error)
+            }
+            Reason::Vulnerable(vulnerability) => {
+                let fixed = vulnerability
+                    .fixed
+                    .as_ref()
+                    .map(|fixed| format!(", fixed in {fixed}"))
+                    .unwrap_or_default();
+                format!(
+                    "{} is vulnerable: {} {}{}",
+                    versioned, vulnerability.id, vulnerability.summary, fixed
+                )
+            }
+            Reason::Outdated => format!(
+                "update {} from {} to {}",
+                self.resource, self.current, proposed
+            ),
+            Reason::StaleFloating => {
+                format!("{versioned} points to an older commit than {proposed}")
+            }
+            Reason::BranchPinned => {
+                format!("{versioned} is pinned to a branch")
+            }
+            Reason::Impostor => {
+                format!("{versioned} is not reachable from any upstream branch or tag")
+            }
+            Reason::LockChanged { target, locked } => {
+                format!("{versioned} now resolves to {target}, locked to {locked}")
+            }
+            Reason::Skipped { check, why } => {
+                format!("{versioned} was not checked for {check}: {why}")
+            }
+        }
+    }
+
+    fn sort_key(&self) -> (&path::Path, Option<Location>, String, usize) {
+        (
+            &self.file,
+            self.location,
+            self.resource.to_string(),
+            self.reason.rank(),
+        )
+    }
+}
+
+/// What was decided about a resource-version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Decision {
+    /// The version is the latest one.
+    Keep,
+    /// The version should be updated to the candidate.
+    Update,
+    /// The versions of the resource couldn't be looked up.
+    Unresolved,
+}
+
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Decision::Keep => write!(f, "keep"),
+            Decision::Update => write!(f, "update"),
+            Decision::Unresolved => write!(f, "unresolved"),
+        }
+    }
+}
+
+/// A resource-version that a workflow uses.
+#[derive(Debug, Clone)]
+pub struct Reference {
+    pub file: path::PathBuf,
+    pub location: Option<Location>,
+    pub resource: Resource,
+    pub current: Version,
+    /// The current version is one of the upstream versions.
+    pub known: bool,
+    /// Latest version of the resource, if it could be looked up.
+    pub candidate: Option<Version>,
+    pub decision: Decision,
+    /// The update was written to the file.
+    pub applied: bool,
+}
+
+impl Reference {
+    fn sort_key(&self) -> (&path::Path, Option<Location>, String) {
+        (&self.file, self.location, self.resource.to_string())
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Report {
+    /// All resource-versions found.
+    pub references: Vec<Reference>,
+    pub findings: Vec<Finding>,
+    /// Files that couldn't be processed, with the error.
+    pub errors: Vec<(path::PathBuf, String)>,
+    /// Statistics of the run, included by the formats that support them.
+    pub stats: Option<Arc<Stats>>,
+}
+
+impl Report {
+    pub fn push(&mut self, finding: Finding) {
+        self.findings.push(finding);
+    }
+
+    pub fn push_reference(&mut self, reference: Reference) {
+        self.references.push(reference);
+    }
+
+    /// Add the references, findings and errors of the other report.
+    pub fn merge(&mut self, other: Report) {
+        self.references.extend(other.references);
+        self.findings.extend(other.findings);
+        self.errors.extend(other.errors);
+    }
+
+    /// Sort the references and findings by file, line and resource.
+    pub fn sort(&mut self) {
+        self.references
+            .sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
+        self.findings
+            .sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
+        self.errors.sort();
+    }
+
+    /// Findings of the reference.
+    pub fn findings_of<'a>(
+        &'a self,
+        reference: &'a Reference,
+    ) -> impl Iterator<Item = &'a Finding> {
+        self.findings.iter().filter(|finding| {
+            finding.file == reference.file
+                && finding.resource == reference.resource
+                && finding.current == reference.current
+        })
+    }
+
+    /// Check if any resource is outdated or vulnerable.
+    pub fn any_outdated(&self) -> bool {
+        self.findings
+            .iter()
+            .any(|finding| matches!(finding.reason, Reason::Outdated | Reason::Vulnerable(_)))
+    }
+
+    /// Check if the versions of any resource couldn't be looked up.
+    pub fn any_unresolved(&self) -> bool {
+        self.findings
+            .iter()
+            .any(|finding| matches!(finding.reason, Reason::Unresolved(_)))
+    }
+}
+
+/// Renders a report in an output format.
+pub trait Formatter {
+    fn format(&self, report: &Report) -> String;
+}
+
+/// Get the formatter of the output format.
+pub fn formatter(output_format: OutputFormat, dryrun: bool) -> Box<dyn Formatter> {
+    match output_format {
+        OutputFormat::Standard => Box::new(standard::Standard { dryrun }),
+        OutputFormat::GithubWarning => Box::new(github::Github),
+        OutputFormat::Json => Box::new(json::Json),
+        OutputFormat::Sarif => Box::new(sarif::Sarif),
+    }
+}
+
+#[test]
+fn test_report() {
+    let contents = "steps:\n  - uses: actions/checkout@v4.1\n  - uses: actions/checkout@v4\n";
+    let checkout = Resource::new_ghaction("actions".into(), "checkout".into());
+    let v4 = Version::new("v4").unwrap();
+    let location = checkout
+        .occurrences(contents, &v4)
+        .first()
+        .map(|range| Location::at(contents, range.start));
+    assert_eq!(
+        location,
+        Some(Location {
+            line: 3,
+            column: 11
+        })
+    );
+    let mut report = Report::default();
+    let file = path::Path::new("ci.yml");
+    let v5 = Version::new("v5").unwrap();
+    report.push(Finding::new(
+        file,
+        location,
+        &checkout,
+        &v4,
+        Some(&v5),
+        Reason::Outdated,
+    ));
+    report.push(Finding::new(
+        file,
+        location,
+        &checkout,
+        &v4,
+        None,
+        Reason::Unresolved(Arc::new(Error::ProxyGone)),
+    ));
+    report.push(Finding::new(
+        path::Path::new("a.yml"),
+        None,
+        &checkout,
+        &v4,
+        Some(&v5),
+        Reason::Outdated,
+    ));
+    report.sort();
+    let order = report
+        .findings
+        .iter()
+        .map(|finding| (finding.file.display().to_string(), finding.severity))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        order,
+        [
+            ("a.yml".to_string(), Severity::Warning),
+            ("ci.yml".to_string(), Severity::Error),
+            ("ci.yml".to_string(), Severity::Warning),
+        ]
+    );
+    assert_eq!(
+        report.findings[2].message(),
+        "update actions/checkout from v4 to v5"
+    );
+    assert!(report.any_outdated());
+    assert!(report.any_unresolved());
+}
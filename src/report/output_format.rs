@@ -0,0 +1,21 @@
+// Copyright (C) 2022 Leandro Lisboa Penz <lpenz@lpenz.org>
+// This file is subject to the terms and conditions defined in
+// file 'LICENSE', which is part of this source code package.
+
+// Also included by build.rs, through src/cli.rs, so it can only use clap
+// and std, and can't have inner attributes.
+
+use clap::ValueEnum;
+
+/// Output format of the [`Report`](crate::report::Report).
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Default, Debug)]
+pub enum OutputFormat {
+    #[default]
+    Standard,
+    /// Generate messages as github action warnings
+    GithubWarning,
+    /// Generate a JSON document with all resources found
+    Json,
+    /// Generate a SARIF 2.1.0 log, for github code scanning
+    Sarif,
+}
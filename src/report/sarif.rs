@@ -0,0 +1,252 @@
+// Copyright (C) 2022 Leandro Lisboa Penz <lpenz@lpenz.org>
+// This file is subject to the terms and conditions defined in
+// file 'LICENSE', which is part of this source code package.
+
+//! [SARIF 2.1.0](https://docs.oasis-open.org/sarif/sarif/v2.1.0/sarif-v2.1.0.html)
+//! output, which github code scanning shows as alerts.
+//!
+//! Each kind of [`Reason`](super::Reason) is a rule, identified by
+//! [`Reason::id`](super::Reason::id).
+//! Findings that propose a version carry a fix that replaces the
+//! resource-version in the workflow.
+//!
+//! Skipped checks are not alerts: they are reported as notifications of
+//! the invocation instead of results.
+
+use serde_json::Value;
+use serde_json::json;
+
+use crate::report::Finding;
+use crate::report::Formatter;
+use crate::report::Reason;
+use crate::report::Report;
+
+/// Rules with their id, description and default level.
+const RULES: &[(&str, &str, &str)] = &[
+    (
+        "unresolved",
+        "The versions of the resource couldn't be looked up",
+        "error",
+    ),
+    (
+        "vulnerable",
+        "The version has a known vulnerability",
+        "error",
+    ),
+    ("outdated", "There is a newer version", "warning"),
+    (
+        "branch-pinned",
+        "The version is a branch instead of a tag or commit",
+        "warning",
+    ),
+    (
+        "stale-floating",
+        "The floating tag doesn't point to the newest matching release",
+        "warning",
+    ),
+    (
+        "impostor",
+        "The pinned commit is not reachable from upstream",
+        "error",
+    ),
+    (
+        "lock-changed",
+        "The version resolves to something else than what was locked",
+        "warning",
+    ),
+];
+
+/// Descriptor of the notifications of skipped checks.
+const SKIPPED: (&str, &str, &str) = ("skipped", "A check of the version couldn't be done", "note");
+
+#[derive(Debug, Default)]
+pub struct Sarif;
+
+fn rule_json(&(id, description, level): &(&str, &str, &str)) -> Value {
+    json!({
+        "id": id,
+        "shortDescription": { "text": description },
+        "defaultConfiguration": { "level": level },
+    })
+}
+
+/// Region of the resource-version in the file, if it was found.
+fn region(finding: &Finding) -> Option<Value> {
+    let location = finding.location?;
+    let length = finding
+        .resource
+        .versioned_string(&finding.current)
+        .chars()
+        .count();
+    Some(json!({
+        "startLine": location.line,
+        "startColumn": location.column,
+        "endColumn": location.column + length,
+    }))
+}
+
+/// Location of the finding, with the region if it was found.
+fn location_json(finding: &Finding) -> Value {
+    let mut physical = json!({
+        "artifactLocation": {
+            "uri": finding.file.display().to_string(),
+            "uriBaseId": "%SRCROOT%",
+        },
+    });
+    if let Some(region) = region(finding) {
+        physical["region"] = region;
+    }
+    json!({ "physicalLocation": physical })
+}
+
+fn result_json(finding: &Finding) -> Value {
+    let id = finding.reason.id();
+    let uri = finding.file.display().to_string();
+    let mut result = json!({
+        "ruleId": id,
+        "ruleIndex": RULES.iter().position(|rule| rule.0 == id),
+        "level": finding.severity.to_string(),
+        "message": { "text": finding.message() },
+        "locations": [location_json(finding)],
+    });
+    if let (Some(region), Some(proposed)) = (region(finding), &finding.proposed) {
+        let replacement = finding.resource.versioned_string(proposed);
+        result["fixes"] = json!([{
+            "description": { "text": format!("Use {replacement}") },
+            "artifactChanges": [{
+                "artifactLocation": { "uri": uri, "uriBaseId": "%SRCROOT%" },
+                "replacements": [{
+                    "deletedRegion": region,
+                    "insertedContent": { "text": replacement },
+                }],
+            }],
+        }]);
+    }
+    result
+}
+
+fn notification_json(finding: &Finding) -> Value {
+    json!({
+        "descriptor": { "id": SKIPPED.0 },
+        "level": SKIPPED.2,
+        "message": { "text": finding.message() },
+        "locations": [location_json(finding)],
+    })
+}
+
+impl Formatter for Sarif {
+    fn format(&self, report: &Report) -> String {
+        let (skipped, findings): (Vec<_>, Vec<_>) = report
+            .findings
+            .iter()
+            .partition(|finding| matches!(finding.reason, Reason::Skipped { .. }));
+        let data = json!({
+            "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+            "version": "2.1.0",
+            "runs": [{
+                "tool": {
+                    "driver": {
+                        "name": env!("CARGO_PKG_NAME"),
+                        "version": env!("CARGO_PKG_VERSION"),
+                        "informationUri": env!("CARGO_PKG_HOMEPAGE"),
+                        "rules": RULES.iter().map(rule_json).collect::<Vec<_>>(),
+                        "notifications": [rule_json(&SKIPPED)],
+                    },
+                },
+                "invocations": [{
+                    "executionSuccessful": report.errors.is_empty(),
+                    "toolExecutionNotifications": skipped
+                        .into_iter()
+                        .map(notification_json)
+                        .collect::<Vec<_>>(),
+                }],
+                "results": findings.into_iter().map(result_json).collect::<Vec<_>>(),
+            }],
+        });
+        let mut contents =
+            serde_json::to_string_pretty(&data).expect("serializing a Value can't fail");
+        contents.push('\n');
+        contents
+    }
+}
+
+#[test]
+fn test_sarif() {
+    use std::path;
+    use std::sync::Arc;
+
+    use crate::error::Error;
+    use crate::report::Location;
+    use crate::report::Reason;
+    use crate::resource::Resource;
+    use crate::version::Version;
+
+    let file = path::Path::new(".github/workflows/ci.yml");
+    let checkout = Resource::new_ghaction("actions".into(), "checkout".into());
+    let v3 = Version::new("v3").unwrap();
+    let v4 = Version::new("v4").unwrap();
+    let location = Some(Location {
+        line: 8,
+        column: 15,
+    });
+    let mut report = Report::default();
+    report.push(Finding::new(
+        file,
+        location,
+        &checkout,
+        &v3,
+        Some(&v4),
+        Reason::Outdated,
+    ));
+    report.push(Finding::new(
+        file,
+        None,
+        &checkout,
+        &v3,
+        None,
+        Reason::Unresolved(Arc::new(Error::ProxyGone)),
+    ));
+    report.push(Finding::new(
+        file,
+        location,
+        &checkout,
+        &v3,
+        None,
+        Reason::Skipped {
+            check: "stale floating tags",
+            why: "not available in offline mode",
+        },
+    ));
+    report.sort();
+    let data: Value = serde_json::from_str(&Sarif.format(&report)).unwrap();
+    assert_eq!(data["version"], "2.1.0");
+    let run = &data["runs"][0];
+    let results = run["results"].as_array().unwrap();
+    assert_eq!(results[1]["ruleId"], "outdated");
+    assert_eq!(
+        run["tool"]["driver"]["rules"][results[1]["ruleIndex"].as_u64().unwrap() as usize]["id"],
+        "outdated"
+    );
+    let region = &results[1]["locations"][0]["physicalLocation"]["region"];
+    assert_eq!(region["startLine"], 8);
+    assert_eq!(region["startColumn"], 15);
+    assert_eq!(region["endColumn"], 15 + "actions/checkout@v3".len());
+    let replacement = &results[1]["fixes"][0]["artifactChanges"][0]["replacements"][0];
+    assert_eq!(replacement["deletedRegion"], *region);
+    assert_eq!(
+        replacement["insertedContent"]["text"],
+        "actions/checkout@v4"
+    );
+    assert_eq!(results[0]["ruleId"], "unresolved");
+    assert_eq!(results[0]["level"], "error");
+    assert!(results[0].get("fixes").is_none());
+    // Skipped checks are notifications instead of results
+    assert_eq!(results.len(), 2);
+    let notifications = run["invocations"][0]["toolExecutionNotifications"]
+        .as_array()
+        .unwrap();
+    assert_eq!(notifications.len(), 1);
+    assert_eq!(notifications[0]["descriptor"]["id"], "skipped");
+    assert_eq!(notifications[0]["level"], "note");
+    assert_eq!(run["invocations"][0]["executionSuccessful"], true);
+}
@@ -0,0 +1,37 @@
+// Copyright (C) 2022 Leandro Lisboa Penz <lpenz@lpenz.org>
+// This file is subject to the terms and conditions defined in
+// file 'LICENSE', which is part of this source code package.
+
+//! Plain text output, one `file: message` line per finding.
+
+use crate::report::Formatter;
+use crate::report::Reason;
+use crate::report::Report;
+
+#[derive(Debug, Default)]
+pub struct Standard {
+    /// Mark updates as not applied.
+    pub dryrun: bool,
+}
+
+impl Formatter for Standard {
+    fn format(&self, report: &Report) -> String {
+        report
+            .findings
+            .iter()
+            .map(|finding| {
+                let dryrun = if self.dryrun && matches!(finding.reason, Reason::Outdated) {
+                    " (dryrun)"
+                } else {
+                    ""
+                };
+                format!(
+                    "{}: {}{}\n",
+                    finding.file.display(),
+                    finding.message(),
+                    dryrun
+                )
+            })
+            .collect()
+    }
+}
@@ -6,13 +6,28 @@
 
 use regex::Regex;
 use std::fmt;
+use std::ops::Range;
+use std::sync::Arc;
 use tracing::instrument;
 use url::Url;
 
 use crate::error::Error;
 use crate::error::Result;
 use crate::updater;
+use crate::updater::Fetched;
+use crate::updater::Updater;
+use crate::updater::http::Validators;
 use crate::version::Version;
+use crate::version::VersionInfo;
+
+/// Kinds of [`Resource`], used to select their updater.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Kind {
+    Docker,
+    GhAction,
+    GhWorkflow,
+    Git,
+}
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub enum Resource {
@@ -28,6 +43,10 @@ pub enum Resource {
         repo: String,
         workflow: String,
     },
+    /// Action referenced by the URL of its git repository.
+    Git {
+        url: String,
+    },
 }
 
 impl Resource {
@@ -50,6 +69,25 @@ impl Resource {
         }
     }
 
+    #[instrument(level = "debug")]
+    pub fn new_git(url: String) -> Resource {
+        Resource::Git { url }
+    }
+
+    pub fn kind(&self) -> Kind {
+        match self {
+            Resource::Docker { .. } => Kind::Docker,
+            Resource::GhAction { .. } => Kind::GhAction,
+            Resource::GhWorkflow { .. } => Kind::GhWorkflow,
+            Resource::Git { .. } => Kind::Git,
+        }
+    }
+
+    /// Lowercase host that serves the versions of the resource.
+    pub fn host(&self, config: &updater::Config) -> Option<String> {
+        Some(self.url(config).ok()?.host_str()?.to_lowercase())
+    }
+
     pub fn is_docker(&self) -> bool {
         matches!(self, Resource::Docker { .. })
     }
@@ -61,6 +99,10 @@ impl Resource {
         )
     }
 
+    pub fn is_git(&self) -> bool {
+        matches!(self, Resource::Git { .. })
+    }
+
     #[instrument(level = "debug")]
     pub fn parse(input: &str) -> Result<(Self, Version), Error> {
         let re_docker = Regex::new(r"^docker://(?P<resource>[^:]+):(?P<version>[^:]+)$").unwrap();
@@ -73,6 +115,16 @@ impl Resource {
                 version,
             ));
         }
+        let re_git = Regex::new(r"^(?P<url>https?://[^@]+)@(?P<version>[^@]+)$").unwrap();
+        if let Some(m) = re_git.captures(input) {
+            let version_str = m.name("version").unwrap().as_str();
+            let version = Version::new(version_str)
+                .ok_or_else(|| Error::VersionParsing(version_str.into()))?;
+            return Ok((
+                Resource::new_git(m.name("url").unwrap().as_str().into()),
+                version,
+            ));
+        }
         let re_ghworkflow = Regex::new(r"^(?P<user>[^/]+)/(?P<repo>[^/]+)/\.github/workflows/(?P<workflow>[^@]+)@(?P<version>[^@]+)$").unwrap();
         if let Some(m) = re_ghworkflow.captures(input) {
             let version_str = m.name("version").unwrap().as_str();
@@ -104,43 +156,178 @@ impl Resource {
         Err(Error::ResourceParseError(input.into()))
     }
 
+    /// Parse a docker image reference as used by job `container`
+    /// entries, which don't have the `docker://` prefix.
     #[instrument(level = "debug")]
-    pub fn url(&self) -> Result<Url> {
-        let url_string = match self {
+    pub fn parse_image(input: &str) -> Result<(Self, Version), Error> {
+        Resource::parse(&format!("docker://{input}"))
+    }
+
+    #[instrument(level = "debug", skip(config))]
+    pub fn url(&self, config: &updater::Config) -> Result<Url> {
+        match self {
             Resource::Docker { container } => {
-                format!("https://registry.hub.docker.com/v2/repositories/{container}/tags")
+                // Official images live under the implicit "library" namespace
+                let namespace = if container.contains('/') {
+                    ""
+                } else {
+                    "library/"
+                };
+                Ok(
+                    updater::http::api_base(&config.docker.api_url).join(&format!(
+                        "v2/repositories/{namespace}{container}/tags?page_size=100"
+                    ))?,
+                )
+            }
+            Resource::GhAction { .. } | Resource::GhWorkflow { .. } => {
+                Ok(self.repo_url(config)?.join("git/matching-refs/tags")?)
+            }
+            Resource::Git { .. } => Ok(updater::git::refs_url(&self.git_url(config)?)),
+        }
+    }
+
+    /// URL of the github API endpoint of the resource's repository.
+    #[instrument(level = "debug", skip(config))]
+    pub fn repo_url(&self, config: &updater::Config) -> Result<Url> {
+        match self {
+            Resource::GhAction { user, repo } | Resource::GhWorkflow { user, repo, .. } => {
+                let api_url = updater::http::api_base(config.github.api_url(self));
+                Ok(api_url.join(&format!("repos/{user}/{repo}/"))?)
             }
-            Resource::GhAction { user, repo } => {
-                format!("https://api.github.com/repos/{user}/{repo}/git/matching-refs/tags")
+            Resource::Docker { .. } | Resource::Git { .. } => {
+                Err(Error::NotGithub(self.to_string()))
             }
-            Resource::GhWorkflow { user, repo, .. } => {
-                format!("https://api.github.com/repos/{user}/{repo}/git/matching-refs/tags")
+        }
+    }
+
+    /// URL of the git repository of the resource.
+    #[instrument(level = "debug", skip(config))]
+    pub fn git_url(&self, config: &updater::Config) -> Result<Url> {
+        match self {
+            Resource::Git { url } => Ok(Url::parse(url)?),
+            Resource::GhAction { user, repo } | Resource::GhWorkflow { user, repo, .. } => {
+                let web_url = updater::github::web_url(config.github.api_url(self))?;
+                Ok(web_url.join(&format!("{user}/{repo}.git"))?)
             }
+            Resource::Docker { .. } => Err(Error::NotGit(self.to_string())),
+        }
+    }
+
+    /// Check if the docker image is in docker hub: its name doesn't start
+    /// with the host of another registry, like `ghcr.io/`.
+    pub fn is_docker_hub(&self) -> bool {
+        let Resource::Docker { container } = self else {
+            return false;
         };
-        Ok(Url::parse(&url_string)?)
+        match container.split_once('/') {
+            Some((first, _)) => !first.contains(['.', ':']) && first != "localhost",
+            None => true,
+        }
     }
 
-    #[instrument(level = "debug")]
-    pub fn versioned_string(&self, version: &Version) -> String {
-        if self.is_docker() {
-            format!("{self}:{version}")
-        } else if self.is_github() {
-            format!("{self}@{version}")
-        } else {
-            panic!("unknown resource type");
+    /// URL of the web page of the resource at the given version, if it
+    /// has one: the github release or the docker hub tags.
+    ///
+    /// Floating tags and commits of github resources don't have a release,
+    /// and only docker hub images have a known tags page.
+    #[instrument(level = "debug", skip(config))]
+    pub fn release_url(&self, config: &updater::Config, version: &Version) -> Option<Url> {
+        match self {
+            Resource::GhAction { user, repo } | Resource::GhWorkflow { user, repo, .. } => {
+                if updater::github::floating_components(version).is_some()
+                    || updater::github::is_sha(version)
+                {
+                    return None;
+                }
+                let web_url = updater::github::web_url(config.github.api_url(self)).ok()?;
+                web_url
+                    .join(&format!("{user}/{repo}/releases/tag/{version}"))
+                    .ok()
+            }
+            Resource::Docker { container } if self.is_docker_hub() => {
+                let page = if container.contains('/') {
+                    format!("r/{container}/tags?name={version}")
+                } else {
+                    format!("_/{container}/tags?name={version}")
+                };
+                Url::parse("https://hub.docker.com/").ok()?.join(&page).ok()
+            }
+            Resource::Docker { .. } | Resource::Git { .. } => None,
         }
     }
 
+    /// String used to reference the resource at the given version in
+    /// workflow files; docker images are matched without the `docker://`
+    /// prefix so that `container` entries are covered too.
     #[instrument(level = "debug")]
-    pub async fn get_versions(&self) -> Result<Vec<Version>> {
-        if self.is_docker() {
-            updater::docker::get_versions(&self.url()?).await
-        } else if self.is_github() {
-            updater::github::get_versions(&self.url()?).await
-        } else {
-            panic!("unknown resource type");
+    pub fn versioned_string(&self, version: &Version) -> String {
+        match self {
+            Resource::Docker { container } => format!("{container}:{version}"),
+            Resource::GhAction { .. } | Resource::GhWorkflow { .. } | Resource::Git { .. } => {
+                format!("{self}@{version}")
+            }
         }
     }
+
+    /// Byte ranges of the references to the resource at the given version
+    /// in the contents of a workflow file, skipping the matches that are
+    /// only part of a longer name or version, like `debian:1` in
+    /// `debian:12` or `node:18` in `mynode:18`.
+    pub fn occurrences(&self, contents: &str, version: &Version) -> Vec<Range<usize>> {
+        let text = self.versioned_string(version);
+        let is_word = |c: char| c.is_ascii_alphanumeric() || "._-".contains(c);
+        contents
+            .match_indices(&text)
+            .map(|(start, _)| start..start + text.len())
+            .filter(|range| {
+                let before = &contents[..range.start];
+                let after = &contents[range.end..];
+                (before.ends_with("docker://")
+                    || !before.ends_with(|c: char| is_word(c) || c == '/'))
+                    && !after.starts_with(is_word)
+            })
+            .collect()
+    }
+
+    /// Get the updater of the resource from the registry.
+    pub fn updater<'a>(&self, config: &'a updater::Config) -> Result<&'a Arc<dyn Updater>> {
+        config
+            .registry
+            .get(config, self)
+            .ok_or_else(|| Error::NoUpdater(self.to_string()))
+    }
+
+    #[instrument(level = "debug", skip(config))]
+    pub async fn get_versions(&self, config: &updater::Config) -> Result<Vec<VersionInfo>> {
+        self.updater(config)?.get_versions(config, self).await
+    }
+
+    /// Get the versions unless they didn't change since the response with
+    /// the given validators.
+    #[instrument(level = "debug", skip(config))]
+    pub async fn get_versions_if_modified(
+        &self,
+        config: &updater::Config,
+        validators: &Validators,
+    ) -> Result<Fetched> {
+        self.updater(config)?
+            .get_versions_if_modified(config, self, validators)
+            .await
+    }
+
+    /// Get the commit sha or image digest that the version resolves to,
+    /// using the provided list of upstream versions.
+    #[instrument(level = "debug", skip(config, versions))]
+    pub async fn resolve(
+        &self,
+        config: &updater::Config,
+        versions: &[VersionInfo],
+        version: &Version,
+    ) -> Result<Option<String>> {
+        self.updater(config)?
+            .resolve(config, self, versions, version)
+            .await
+    }
 }
 
 impl fmt::Display for Resource {
@@ -156,7 +343,72 @@ impl fmt::Display for Resource {
                     repo,
                     workflow,
                 } => format!("{user}/{repo}/.github/workflows/{workflow}"),
+                Resource::Git { url } => url.clone(),
             }
         )
     }
 }
+
+#[test]
+fn test_occurrences() {
+    let debian = Resource::new_docker("debian".into());
+    let node = Resource::new_docker("node".into());
+    let checkout = Resource::new_ghaction("actions".into(), "checkout".into());
+    let contents = "\
+      - uses: docker://debian:12
+      - uses: docker://debian:1
+    container: mynode:18
+    container: node:18
+      - uses: actions/checkout@v3.1
+      - uses: myactions/checkout@v3
+";
+    let v = |s: &str| Version::new(s).unwrap();
+    let lines = |ranges: Vec<Range<usize>>| {
+        ranges
+            .into_iter()
+            .map(|range| contents[..range.start].lines().count())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(lines(debian.occurrences(contents, &v("1"))), [2]);
+    assert_eq!(lines(debian.occurrences(contents, &v("12"))), [1]);
+    assert_eq!(lines(node.occurrences(contents, &v("18"))), [4]);
+    assert!(checkout.occurrences(contents, &v("v3")).is_empty());
+}
+
+#[test]
+fn test_repo_url() -> Result<()> {
+    let mut config = updater::Config::default();
+    config.github.api_urls.insert(
+        "corp".into(),
+        Url::parse("https://ghes.example.com/api/v3")?,
+    );
+    let checkout = Resource::new_ghaction("actions".into(), "checkout".into());
+    assert_eq!(
+        checkout.repo_url(&config)?.as_str(),
+        "https://api.github.com/repos/actions/checkout/"
+    );
+    let internal = Resource::new_ghaction("Corp".into(), "deploy".into());
+    assert_eq!(
+        internal.url(&config)?.as_str(),
+        "https://ghes.example.com/api/v3/repos/Corp/deploy/git/matching-refs/tags"
+    );
+    assert_eq!(
+        internal.git_url(&config)?.as_str(),
+        "https://ghes.example.com/Corp/deploy.git"
+    );
+    assert_eq!(
+        checkout.git_url(&config)?.as_str(),
+        "https://github.com/actions/checkout.git"
+    );
+    let (gitea, version) = Resource::parse("https://gitea.example.com/actions/checkout@v4")?;
+    assert_eq!(
+        gitea,
+        Resource::new_git("https://gitea.example.com/actions/checkout".into())
+    );
+    assert_eq!(version, Version::new("v4").unwrap());
+    assert_eq!(
+        gitea.url(&config)?.as_str(),
+        "https://gitea.example.com/actions/checkout/info/refs?service=git-upload-pack"
+    );
+    Ok(())
+}
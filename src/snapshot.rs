@@ -0,0 +1,184 @@
+// Copyright (C) 2022 Leandro Lisboa Penz <lpenz@lpenz.org>
+// This file is subject to the terms and conditions defined in
+// file 'LICENSE', which is part of this source code package.
+
+//! The [`Snapshot`] records the versions of resources, so that later runs
+//! can use them without network access.
+//!
+//! The snapshot is also the [`Updater`] of all resources in offline mode,
+//! which makes lookups of resources that are not in it fail.
+
+use async_trait::async_trait;
+use color_eyre::{Result, eyre::eyre};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+use tracing::instrument;
+
+use crate::error;
+use crate::error::Error;
+use crate::resource::Resource;
+use crate::updater;
+use crate::updater::Updater;
+use crate::version::Version;
+use crate::version::VersionInfo;
+
+/// Versions of a resource, along with where they came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    /// Name of the updater that got the versions.
+    updater: String,
+    versions: Vec<VersionInfo>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Snapshot {
+    /// Entries indexed by resource.
+    entries: BTreeMap<String, Entry>,
+}
+
+impl Snapshot {
+    #[instrument(level="debug", fields(filename = ?filename.as_ref().display()))]
+    pub async fn read(filename: impl AsRef<path::Path>) -> Result<Snapshot> {
+        let contents = tokio::fs::read_to_string(filename).await?;
+        Ok(Snapshot {
+            entries: parse(&contents)?,
+        })
+    }
+
+    #[instrument(level="debug", skip(self), fields(filename = ?filename.as_ref().display()))]
+    pub async fn write(&self, filename: impl AsRef<path::Path>) -> Result<()> {
+        let resources = self
+            .entries
+            .iter()
+            .map(|(resource, entry)| {
+                let versions = entry
+                    .versions
+                    .iter()
+                    .map(|info| {
+                        serde_json::json!({
+                            "version": info.version.string,
+                            "target": info.target,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                let entry = serde_json::json!({
+                    "updater": entry.updater,
+                    "versions": versions,
+                });
+                (resource.clone(), entry)
+            })
+            .collect::<serde_json::Map<_, _>>();
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let data = serde_json::json!({
+            "version": 1,
+            "generator": updater::http::USER_AGENT,
+            "created": created,
+            "resources": resources,
+        });
+        let mut contents = serde_json::to_string_pretty(&data)?;
+        contents.push('\n');
+        tokio::fs::write(filename, contents).await?;
+        Ok(())
+    }
+
+    /// Record the versions of the resource, as got by the named updater.
+    pub fn insert(&mut self, resource: &Resource, updater: &str, versions: Vec<VersionInfo>) {
+        self.entries.insert(
+            resource.to_string(),
+            Entry {
+                updater: updater.into(),
+                versions,
+            },
+        );
+    }
+
+    pub fn get(&self, resource: &Resource) -> Option<&[VersionInfo]> {
+        self.entries
+            .get(&resource.to_string())
+            .map(|entry| entry.versions.as_slice())
+    }
+}
+
+fn parse(contents: &str) -> Result<BTreeMap<String, Entry>> {
+    let data: Value = serde_json::from_str(contents)?;
+    let resources = data
+        .get("resources")
+        .ok_or_else(|| eyre!("resources entry not found in snapshot"))?
+        .as_object()
+        .ok_or_else(|| eyre!("invalid type for resources entry in snapshot"))?;
+    resources
+        .iter()
+        .map(|(resource, entry)| {
+            let updater = entry
+                .get("updater")
+                .and_then(|updater| updater.as_str())
+                .unwrap_or_default()
+                .to_string();
+            let versions = entry
+                .get("versions")
+                .and_then(|versions| versions.as_array())
+                .ok_or_else(|| eyre!("versions of {resource} not found in snapshot"))?
+                .iter()
+                .map(|info| {
+                    let version = info
+                        .get("version")
+                        .and_then(|version| version.as_str())
+                        .and_then(Version::new)
+                        .ok_or_else(|| eyre!("invalid version of {resource} in snapshot"))?;
+                    let target = info
+                        .get("target")
+                        .and_then(|target| target.as_str())
+                        .map(String::from);
+                    Ok(VersionInfo::new(version, target))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Ok((resource.clone(), Entry { updater, versions }))
+        })
+        .collect()
+}
+
+#[async_trait]
+impl Updater for Snapshot {
+    fn name(&self) -> &str {
+        "snapshot"
+    }
+
+    async fn get_versions(
+        &self,
+        _config: &updater::Config,
+        resource: &Resource,
+    ) -> error::Result<Vec<VersionInfo>> {
+        self.get(resource)
+            .map(|versions| versions.to_vec())
+            .ok_or_else(|| Error::NotInSnapshot(resource.to_string()))
+    }
+}
+
+#[tokio::test]
+async fn test_snapshot() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let filename = dir.path().join("snapshot.json");
+    let checkout = Resource::new_ghaction("actions".into(), "checkout".into());
+    let debian = Resource::new_docker("debian".into());
+    let versions = vec![
+        VersionInfo::new(Version::new("v4").unwrap(), Some("aaa".into())),
+        VersionInfo::new(Version::new("v3").unwrap(), None),
+    ];
+    let mut snapshot = Snapshot::default();
+    snapshot.insert(&checkout, "github", versions.clone());
+    snapshot.write(&filename).await?;
+    let snapshot = Snapshot::read(&filename).await?;
+    let config = updater::Config::default();
+    assert_eq!(snapshot.get_versions(&config, &checkout).await?, versions);
+    assert!(matches!(
+        snapshot.get_versions(&config, &debian).await,
+        Err(Error::NotInSnapshot(_))
+    ));
+    Ok(())
+}
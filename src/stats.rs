@@ -0,0 +1,275 @@
+// Copyright (C) 2022 Leandro Lisboa Penz <lpenz@lpenz.org>
+// This file is subject to the terms and conditions defined in
+// file 'LICENSE', which is part of this source code package.
+
+//! [`Stats`] of a run: how lookups were answered, the HTTP requests made
+//! to each host and the rate limit budget that the run left.
+
+use reqwest::header::HeaderMap;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Requests made to a host.
+#[derive(Debug, Default, Clone)]
+struct Host {
+    requests: u64,
+    retries: u64,
+    bytes: u64,
+    /// Time until the response headers of each request arrived.
+    latencies: Vec<Duration>,
+}
+
+/// Rate limit budget reported by github.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RateLimit {
+    limit: u64,
+    remaining: u64,
+    /// When the budget is reset, in seconds since the epoch.
+    reset: u64,
+}
+
+#[derive(Debug, Default, Clone)]
+struct Counters {
+    /// Lookups requested by clients.
+    lookups: u64,
+    /// Lookups answered from memory.
+    cache_hits: u64,
+    /// Lookups answered by the disk cache.
+    disk_cache_hits: u64,
+    /// Lookups that joined one already in flight.
+    deduplicated: u64,
+    /// Resources that couldn't be looked up.
+    errors: u64,
+    hosts: BTreeMap<String, Host>,
+    /// Rate limits by host and github rate limit resource.
+    rate_limits: BTreeMap<(String, String), RateLimit>,
+}
+
+/// Statistics shared by the proxy server and the updaters.
+#[derive(Debug, Default)]
+pub struct Stats {
+    counters: Mutex<Counters>,
+}
+
+/// Value of the given percentile of the sorted durations.
+fn percentile(sorted: &[Duration], percent: usize) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    // Nearest-rank method
+    let rank = (sorted.len() * percent).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+fn header(headers: &HeaderMap, name: &str) -> Option<u64> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+}
+
+impl Stats {
+    fn update(&self, f: impl FnOnce(&mut Counters)) {
+        f(&mut self.counters.lock().expect("poisoned stats"));
+    }
+
+    pub fn lookup(&self) {
+        self.update(|c| c.lookups += 1);
+    }
+
+    pub fn cache_hit(&self) {
+        self.update(|c| c.cache_hits += 1);
+    }
+
+    pub fn disk_cache_hit(&self) {
+        self.update(|c| c.disk_cache_hits += 1);
+    }
+
+    pub fn deduplicated(&self) {
+        self.update(|c| c.deduplicated += 1);
+    }
+
+    pub fn error(&self) {
+        self.update(|c| c.errors += 1);
+    }
+
+    /// Record a request to the host, answered after `latency`, along with
+    /// the rate limit that the response reports.
+    pub fn request(&self, host: &str, latency: Duration, headers: &HeaderMap) {
+        let rate_limit = match (
+            header(headers, "x-ratelimit-limit"),
+            header(headers, "x-ratelimit-remaining"),
+            header(headers, "x-ratelimit-reset"),
+        ) {
+            (Some(limit), Some(remaining), Some(reset)) => Some(RateLimit {
+                limit,
+                remaining,
+                reset,
+            }),
+            _ => None,
+        };
+        let resource = headers
+            .get("x-ratelimit-resource")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("core")
+            .to_string();
+        self.update(|c| {
+            let stats = c.hosts.entry(host.into()).or_default();
+            stats.requests += 1;
+            stats.latencies.push(latency);
+            let Some(rate_limit) = rate_limit else {
+                return;
+            };
+            // Responses may arrive out of order; the lowest budget of the
+            // latest window is the current one
+            c.rate_limits
+                .entry((host.into(), resource))
+                .and_modify(|current| {
+                    if (rate_limit.reset, current.remaining) > (current.reset, rate_limit.remaining)
+                    {
+                        *current = rate_limit.clone();
+                    }
+                })
+                .or_insert(rate_limit);
+        });
+    }
+
+    pub fn retry(&self, host: &str) {
+        self.update(|c| c.hosts.entry(host.into()).or_default().retries += 1);
+    }
+
+    pub fn bytes(&self, host: &str, bytes: u64) {
+        self.update(|c| c.hosts.entry(host.into()).or_default().bytes += bytes);
+    }
+
+    fn snapshot(&self) -> Counters {
+        let mut counters = self.counters.lock().expect("poisoned stats").clone();
+        for host in counters.hosts.values_mut() {
+            host.latencies.sort();
+        }
+        counters
+    }
+
+    /// Statistics as a JSON object, with latencies in milliseconds.
+    pub fn to_json(&self) -> Value {
+        let counters = self.snapshot();
+        let ms = |duration: Duration| duration.as_millis() as u64;
+        let hosts = counters
+            .hosts
+            .iter()
+            .map(|(name, host)| {
+                let stats = serde_json::json!({
+                    "requests": host.requests,
+                    "retries": host.retries,
+                    "bytes": host.bytes,
+                    "latency_ms": {
+                        "p50": ms(percentile(&host.latencies, 50)),
+                        "p90": ms(percentile(&host.latencies, 90)),
+                        "p99": ms(percentile(&host.latencies, 99)),
+                        "max": ms(host.latencies.last().copied().unwrap_or_default()),
+                    },
+                });
+                (name.clone(), stats)
+            })
+            .collect::<serde_json::Map<_, _>>();
+        let rate_limits = counters
+            .rate_limits
+            .iter()
+            .map(|((host, resource), rate_limit)| {
+                serde_json::json!({
+                    "host": host,
+                    "resource": resource,
+                    "limit": rate_limit.limit,
+                    "remaining": rate_limit.remaining,
+                    "reset": rate_limit.reset,
+                })
+            })
+            .collect::<Vec<_>>();
+        serde_json::json!({
+            "lookups": counters.lookups,
+            "cache_hits": counters.cache_hits,
+            "disk_cache_hits": counters.disk_cache_hits,
+            "deduplicated": counters.deduplicated,
+            "errors": counters.errors,
+            "hosts": hosts,
+            "rate_limits": rate_limits,
+        })
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let counters = self.snapshot();
+        writeln!(
+            f,
+            "lookups: {}, cache hits: {}, disk cache hits: {}, deduplicated: {}, errors: {}",
+            counters.lookups,
+            counters.cache_hits,
+            counters.disk_cache_hits,
+            counters.deduplicated,
+            counters.errors
+        )?;
+        for (name, host) in &counters.hosts {
+            writeln!(
+                f,
+                "{}: {} requests, {} retries, {} bytes, latency p50 {:?} p90 {:?} p99 {:?}",
+                name,
+                host.requests,
+                host.retries,
+                host.bytes,
+                percentile(&host.latencies, 50),
+                percentile(&host.latencies, 90),
+                percentile(&host.latencies, 99),
+            )?;
+        }
+        for ((host, resource), rate_limit) in &counters.rate_limits {
+            writeln!(
+                f,
+                "{} {} rate limit: {} of {} remaining, reset at {}",
+                host, resource, rate_limit.remaining, rate_limit.limit, rate_limit.reset
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_stats() {
+    let ms = Duration::from_millis;
+    let latencies = (1..=100).map(ms).collect::<Vec<_>>();
+    assert_eq!(percentile(&latencies, 50), ms(50));
+    assert_eq!(percentile(&latencies, 99), ms(99));
+    assert_eq!(percentile(&[ms(7)], 90), ms(7));
+    assert_eq!(percentile(&[], 90), Duration::ZERO);
+    let stats = Stats::default();
+    stats.lookup();
+    stats.lookup();
+    stats.cache_hit();
+    let mut headers = HeaderMap::new();
+    headers.insert("x-ratelimit-limit", "5000".parse().unwrap());
+    headers.insert("x-ratelimit-remaining", "4990".parse().unwrap());
+    headers.insert("x-ratelimit-reset", "1000".parse().unwrap());
+    stats.request("api.github.com", ms(30), &headers);
+    headers.insert("x-ratelimit-remaining", "4995".parse().unwrap());
+    stats.request("api.github.com", ms(10), &headers);
+    stats.retry("api.github.com");
+    stats.bytes("api.github.com", 2048);
+    let json = stats.to_json();
+    assert_eq!(json["lookups"], 2);
+    assert_eq!(json["cache_hits"], 1);
+    assert_eq!(
+        json["hosts"]["api.github.com"],
+        serde_json::json!({
+            "requests": 2,
+            "retries": 1,
+            "bytes": 2048,
+            "latency_ms": {"p50": 10, "p90": 30, "p99": 30, "max": 30},
+        })
+    );
+    assert_eq!(json["rate_limits"][0]["resource"], "core");
+    assert_eq!(json["rate_limits"][0]["remaining"], 4990);
+    assert!(stats.to_string().contains("4990 of 5000 remaining"));
+}
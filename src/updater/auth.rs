@@ -0,0 +1,178 @@
+// Copyright (C) 2022 Leandro Lisboa Penz <lpenz@lpenz.org>
+// This file is subject to the terms and conditions defined in
+// file 'LICENSE', which is part of this source code package.
+
+//! Per-host github [`Credentials`], loaded from the environment, the `gh`
+//! CLI configuration and `~/.netrc`.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path;
+use tracing::Level;
+use tracing::event;
+use tracing::instrument;
+use url::Url;
+
+/// A secret token; its `Debug` output is redacted so that it doesn't end
+/// up in logs and tracing spans.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Token(String);
+
+impl Token {
+    pub fn new(token: impl Into<String>) -> Token {
+        Token(token.into())
+    }
+
+    /// The actual secret.
+    pub fn secret(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Token(<redacted>)")
+    }
+}
+
+/// Tokens indexed by host.
+#[derive(Debug, Default, Clone)]
+pub struct Credentials {
+    tokens: HashMap<String, Token>,
+}
+
+/// Environment variables with tokens for the default github API host, in
+/// increasing order of priority.
+const TOKEN_VARS: [&str; 3] = ["PERSONAL_TOKEN", "GH_TOKEN", "GITHUB_TOKEN"];
+
+impl Credentials {
+    /// Load the credentials from the usual sources, in increasing order of
+    /// priority: `~/.netrc`, the `gh` CLI `hosts.yml` and the
+    /// `PERSONAL_TOKEN`, `GH_TOKEN` and `GITHUB_TOKEN` environment
+    /// variables, which apply to the host of `api_url`.
+    #[instrument(level = "debug")]
+    pub async fn load(api_url: &Url) -> Credentials {
+        let mut credentials = Credentials::default();
+        if let Some(netrc) = netrc_path()
+            && let Ok(contents) = tokio::fs::read_to_string(&netrc).await
+        {
+            credentials.tokens.extend(parse_netrc(&contents));
+        }
+        if let Some(hosts) = gh_hosts_path()
+            && let Ok(contents) = tokio::fs::read_to_string(&hosts).await
+        {
+            match parse_gh_hosts(&contents) {
+                Ok(tokens) => credentials.tokens.extend(tokens),
+                Err(e) => {
+                    event!(
+                        Level::WARN,
+                        filename = ?hosts,
+                        error = %e,
+                        "error parsing gh hosts file"
+                    );
+                }
+            }
+        }
+        if let Some(host) = api_url.host_str() {
+            for var in TOKEN_VARS {
+                if let Ok(token) = std::env::var(var)
+                    && !token.is_empty()
+                {
+                    credentials.insert(host, Token::new(token));
+                }
+            }
+        }
+        credentials
+    }
+
+    pub fn insert(&mut self, host: &str, token: Token) {
+        self.tokens.insert(normalize_host(host), token);
+    }
+
+    /// Get the token for the host of the URL.
+    pub fn token(&self, url: &Url) -> Option<&Token> {
+        self.tokens.get(&normalize_host(url.host_str()?))
+    }
+}
+
+/// The API of github.com lives in api.github.com, while `gh` and netrc
+/// entries usually use the web host.
+fn normalize_host(host: &str) -> String {
+    let host = host.to_lowercase();
+    match host.as_str() {
+        "api.github.com" => "github.com".into(),
+        _ => host,
+    }
+}
+
+fn netrc_path() -> Option<path::PathBuf> {
+    if let Some(netrc) = std::env::var_os("NETRC") {
+        return Some(netrc.into());
+    }
+    Some(path::PathBuf::from(std::env::var_os("HOME")?).join(".netrc"))
+}
+
+fn gh_hosts_path() -> Option<path::PathBuf> {
+    if let Some(dir) = std::env::var_os("GH_CONFIG_DIR") {
+        return Some(path::PathBuf::from(dir).join("hosts.yml"));
+    }
+    let config = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(config) => path::PathBuf::from(config),
+        None => path::PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(config.join("gh").join("hosts.yml"))
+}
+
+/// Parse the `machine ... password ...` entries of a netrc file.
+fn parse_netrc(contents: &str) -> HashMap<String, Token> {
+    let mut tokens = HashMap::new();
+    let mut machine: Option<String> = None;
+    let mut words = contents.split_whitespace();
+    while let Some(word) = words.next() {
+        match word {
+            "machine" => machine = words.next().map(normalize_host),
+            "default" => machine = None,
+            "password" => {
+                if let (Some(host), Some(password)) = (&machine, words.next()) {
+                    tokens.insert(host.clone(), Token::new(password));
+                }
+            }
+            _ => {}
+        }
+    }
+    tokens
+}
+
+/// Parse the `oauth_token` entries of the `gh` CLI `hosts.yml`.
+fn parse_gh_hosts(contents: &str) -> Result<HashMap<String, Token>, serde_norway::Error> {
+    let data: serde_norway::Mapping = serde_norway::from_str(contents)?;
+    Ok(data
+        .iter()
+        .filter_map(|(host, entry)| {
+            let token = entry.get("oauth_token")?.as_str()?;
+            Some((normalize_host(host.as_str()?), Token::new(token)))
+        })
+        .collect())
+}
+
+#[test]
+fn test_auth_parse() -> Result<(), serde_norway::Error> {
+    let netrc = parse_netrc(
+        "machine api.github.com login x-access-token password ghp_netrc\n\
+         machine ghes.example.com\n  login me\n  password ghp_ghes\n",
+    );
+    let hosts = parse_gh_hosts(
+        "github.com:\n    oauth_token: gho_gh\n    user: me\n    git_protocol: https\n",
+    )?;
+    let mut credentials = Credentials::default();
+    credentials.tokens.extend(netrc);
+    credentials.tokens.extend(hosts);
+    let api = Url::parse("https://api.github.com/repos/actions/checkout/").unwrap();
+    let ghes = Url::parse("https://ghes.example.com/api/v3/repos/corp/deploy/").unwrap();
+    let other = Url::parse("https://example.com/").unwrap();
+    assert_eq!(credentials.token(&api).unwrap().secret(), "gho_gh");
+    assert_eq!(credentials.token(&ghes).unwrap().secret(), "ghp_ghes");
+    assert!(credentials.token(&other).is_none());
+    assert!(!format!("{credentials:?}").contains("gho_gh"));
+    Ok(())
+}
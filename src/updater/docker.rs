@@ -2,24 +2,58 @@
 // This file is subject to the terms and conditions defined in
 // file 'LICENSE', which is part of this source code package.
 
+use async_trait::async_trait;
 use tracing::instrument;
 use url::Url;
 
 use crate::error::Error;
 use crate::error::Result;
+use crate::resource::Resource;
+use crate::updater;
+use crate::updater::Fetched;
+use crate::updater::Updater;
+use crate::updater::http::Validators;
 use crate::version::Version;
+use crate::version::VersionInfo;
 
-#[instrument(level = "debug")]
-async fn get_json(url: &Url) -> Result<serde_json::Value> {
-    let response = reqwest::get(url.as_str()).await?;
+/// Configuration of the docker updater.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Base URL of the docker hub API.
+    pub api_url: Url,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            api_url: Url::parse("https://registry.hub.docker.com").expect("valid docker hub URL"),
+        }
+    }
+}
+
+#[instrument(level = "debug", skip(config))]
+async fn get_json_if_modified(
+    config: &updater::Config,
+    url: &Url,
+    validators: &Validators,
+) -> Result<Option<(serde_json::Value, Validators)>> {
+    let request = config.client.get(url.as_str());
+    let Some(response) = updater::http::send_if_modified(config, url, request, validators).await?
+    else {
+        return Ok(None);
+    };
     if !response.status().is_success() {
         return Err(Error::HttpError(url.clone(), response.status()));
     }
-    Ok(response.json::<serde_json::Value>().await?)
+    let validators = Validators::from_response(&response);
+    Ok(Some((
+        updater::http::json(config, url, response).await?,
+        validators,
+    )))
 }
 
 #[instrument(level = "debug")]
-fn parse_versions(data: serde_json::Value) -> Result<Vec<Version>> {
+fn parse_versions(data: serde_json::Value) -> Result<Vec<VersionInfo>> {
     data.as_object()
         .ok_or_else(|| Error::JsonParsing("invalid type for top object".into()))?
         .get("results")
@@ -28,9 +62,10 @@ fn parse_versions(data: serde_json::Value) -> Result<Vec<Version>> {
         .ok_or_else(|| Error::JsonParsing("invalid type for \"results\" list".into()))?
         .iter()
         .map(|result| {
-            result
+            let result = result
                 .as_object()
-                .ok_or_else(|| Error::JsonParsing("invalid type for \"result\" object".into()))?
+                .ok_or_else(|| Error::JsonParsing("invalid type for \"result\" object".into()))?;
+            let version = result
                 .get("name")
                 .ok_or_else(|| {
                     Error::JsonParsing("\"name\" field not found in \"result\" object".into())
@@ -43,16 +78,84 @@ fn parse_versions(data: serde_json::Value) -> Result<Vec<Version>> {
                     })?;
                     Version::new(version_str)
                         .ok_or_else(|| Error::VersionParsing(version_str.into()))
-                })?
+                })??;
+            let digest = result
+                .get("digest")
+                .and_then(|digest| digest.as_str())
+                .map(String::from);
+            Ok(VersionInfo::new(version, digest))
         })
-        .collect::<Result<Vec<Version>>>()
+        .collect::<Result<Vec<VersionInfo>>>()
+}
+
+/// Get the versions of the image unless they didn't change since the
+/// response with the given validators.
+#[instrument(level = "debug", skip(config))]
+pub async fn get_versions_if_modified(
+    config: &updater::Config,
+    url: &Url,
+    validators: &Validators,
+) -> Result<Fetched> {
+    match get_json_if_modified(config, url, validators).await? {
+        Some((data, validators)) => Ok(Fetched::Modified(parse_versions(data)?, validators)),
+        None => Ok(Fetched::NotModified),
+    }
+}
+
+/// Updater of docker hub images, with the digests of the tags as their
+/// targets.
+#[derive(Debug)]
+pub struct Docker;
+
+#[async_trait]
+impl Updater for Docker {
+    fn name(&self) -> &str {
+        "docker"
+    }
+
+    async fn get_versions(
+        &self,
+        config: &updater::Config,
+        resource: &Resource,
+    ) -> Result<Vec<VersionInfo>> {
+        let validators = Validators::default();
+        let fetched = self
+            .get_versions_if_modified(config, resource, &validators)
+            .await?;
+        Ok(fetched.into_versions().unwrap_or_default())
+    }
+
+    async fn get_versions_if_modified(
+        &self,
+        config: &updater::Config,
+        resource: &Resource,
+        validators: &Validators,
+    ) -> Result<Fetched> {
+        // Images of other registries would be looked up in docker hub
+        // under the wrong name
+        if !resource.is_docker_hub() {
+            return Err(Error::NotDockerHub(resource.to_string()));
+        }
+        get_versions_if_modified(config, &resource.url(config)?, validators).await
+    }
 }
 
+/// Find the highest explicit version that has the same digest as the
+/// `floating` tag (usually `latest`).
 #[instrument(level = "debug")]
-pub async fn get_versions(url: &Url) -> Result<Vec<Version>> {
-    let data = get_json(url).await?;
-    let versions = parse_versions(data)?;
-    Ok(versions)
+pub fn pin_floating(versions: &[VersionInfo], floating: &Version) -> Option<Version> {
+    let digest = versions
+        .iter()
+        .find(|info| &info.version == floating)?
+        .target
+        .as_ref()?;
+    versions
+        .iter()
+        .filter(|info| info.target.as_ref() == Some(digest))
+        .filter(|info| info.version.version.is_some())
+        .map(|info| &info.version)
+        .max()
+        .cloned()
 }
 
 #[test]
@@ -61,7 +164,7 @@ fn test_docker_parse_versions() -> Result<()> {
     let json_value: serde_json::Value = serde_json::from_str(json_str)?;
     let versions = parse_versions(json_value)?
         .into_iter()
-        .map(|v| format!("{}", v))
+        .map(|v| format!("{}", v.version))
         .collect::<Vec<_>>();
     assert_eq!(
         versions,
@@ -71,3 +174,31 @@ fn test_docker_parse_versions() -> Result<()> {
     );
     Ok(())
 }
+
+#[test]
+fn test_docker_pin_floating() -> Result<()> {
+    let json_str = r#"{"results":[{"name": "latest", "digest": "sha256:bbb"}, {"name": "0.9.0", "digest": "sha256:aaa"}, {"name": "0.8", "digest": "sha256:bbb"}, {"name": "0.8.1", "digest": "sha256:bbb"}, {"name": "0.7"}]}"#;
+    let json_value: serde_json::Value = serde_json::from_str(json_str)?;
+    let versions = parse_versions(json_value)?;
+    let latest = Version::new("latest").unwrap();
+    assert_eq!(
+        pin_floating(&versions, &latest),
+        Some(Version::new("0.8.1").unwrap())
+    );
+    assert_eq!(pin_floating(&versions[4..], &latest), None);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_docker_not_docker_hub() {
+    let config = updater::Config {
+        offline: true,
+        ..Default::default()
+    };
+    // Images of other registries are refused before any request
+    let resource = Resource::new_docker("ghcr.io/lpenz/omnilint".into());
+    assert!(matches!(
+        Docker.get_versions(&config, &resource).await,
+        Err(Error::NotDockerHub(_))
+    ));
+}
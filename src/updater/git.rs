@@ -0,0 +1,247 @@
+// Copyright (C) 2022 Leandro Lisboa Penz <lpenz@lpenz.org>
+// This file is subject to the terms and conditions defined in
+// file 'LICENSE', which is part of this source code package.
+
+//! Tags of any git repository served over HTTP, listed the same way as
+//! `git ls-remote` does: with the `info/refs` advertisement of the git
+//! smart-HTTP protocol.
+//!
+//! Servers that only support the dumb protocol answer the same URL with
+//! a plain `sha<TAB>ref` list, which we parse as well.
+
+use async_trait::async_trait;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::header::USER_AGENT;
+use std::collections::HashMap;
+use tracing::instrument;
+use url::Url;
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::resource::Resource;
+use crate::updater;
+use crate::updater::Updater;
+use crate::version::Version;
+use crate::version::VersionInfo;
+
+/// Content type of the smart-HTTP ref advertisement.
+const ADVERTISEMENT: &str = "application/x-git-upload-pack-advertisement";
+
+/// Get the URL of the ref advertisement of the repository.
+pub fn refs_url(repo_url: &Url) -> Url {
+    let mut url = repo_url.clone();
+    let path = url.path().trim_end_matches('/').to_string();
+    url.set_path(&format!("{path}/info/refs"));
+    url.set_query(Some("service=git-upload-pack"));
+    url
+}
+
+/// Parse the pkt-line encoded ref advertisement into `(ref, sha)` pairs.
+fn parse_advertisement(data: &[u8]) -> Result<Vec<(String, String)>> {
+    let invalid = |reason: &str| Error::GitProtocol(reason.into());
+    let mut refs = vec![];
+    let mut rest = data;
+    while !rest.is_empty() {
+        let len = rest
+            .get(..4)
+            .and_then(|len| std::str::from_utf8(len).ok())
+            .and_then(|len| usize::from_str_radix(len, 16).ok())
+            .ok_or_else(|| invalid("invalid pkt-line length"))?;
+        if len == 0 {
+            // Flush packet
+            rest = &rest[4..];
+            continue;
+        }
+        let line = rest
+            .get(4..len)
+            .ok_or_else(|| invalid("truncated pkt-line"))?;
+        rest = &rest[len..];
+        let line = String::from_utf8_lossy(line);
+        // The first ref carries the capabilities after a NUL
+        let line = line.split('\0').next().unwrap_or_default().trim_end();
+        if line.starts_with('#') {
+            continue;
+        }
+        if let Some((sha, name)) = line.split_once(' ') {
+            refs.push((name.to_string(), sha.to_string()));
+        }
+    }
+    Ok(refs)
+}
+
+/// Parse the `sha<TAB>ref` lines of the dumb protocol.
+fn parse_dumb(data: &[u8]) -> Vec<(String, String)> {
+    String::from_utf8_lossy(data)
+        .lines()
+        .filter_map(|line| {
+            let (sha, name) = line.split_once('\t')?;
+            Some((name.to_string(), sha.to_string()))
+        })
+        .collect()
+}
+
+/// Get the tags out of the refs, using the commit that annotated tags
+/// point to as their target.
+fn parse_tags(refs: Vec<(String, String)>) -> Result<Vec<VersionInfo>> {
+    let mut peeled = HashMap::new();
+    let mut tags = vec![];
+    for (name, sha) in refs {
+        let Some(tag) = name.strip_prefix("refs/tags/") else {
+            continue;
+        };
+        match tag.strip_suffix("^{}") {
+            Some(tag) => {
+                peeled.insert(tag.to_string(), sha);
+            }
+            None => tags.push((tag.to_string(), sha)),
+        }
+    }
+    tags.into_iter()
+        .map(|(tag, sha)| {
+            let version = Version::new(&tag).ok_or_else(|| Error::VersionParsing(tag.clone()))?;
+            let target = peeled.remove(&tag).unwrap_or(sha);
+            Ok(VersionInfo::new(version, Some(target)))
+        })
+        .collect()
+}
+
+/// Get the tags of the git repository.
+///
+/// A token for the host, when we have one, is sent as the password of
+/// basic authentication, which is what most git servers accept.
+#[instrument(level = "debug", skip(config))]
+pub async fn get_versions(config: &updater::Config, repo_url: &Url) -> Result<Vec<VersionInfo>> {
+    let url = refs_url(repo_url);
+    // Some servers only speak the smart protocol to git clients
+    let mut builder = config
+        .client
+        .get(url.as_str())
+        .header(USER_AGENT, format!("git/2.0 ({})", config.user_agent));
+    if let Some(token) = config.github.credentials.token(&url) {
+        builder = builder.basic_auth("x-access-token", Some(token.secret()));
+    }
+    let response = updater::http::send(config, &url, builder).await?;
+    if !response.status().is_success() {
+        return Err(Error::HttpError(url, response.status()));
+    }
+    let smart = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .is_some_and(|content_type| content_type == ADVERTISEMENT);
+    let data = updater::http::bytes(config, &url, response).await?;
+    let refs = if smart {
+        parse_advertisement(&data)?
+    } else {
+        parse_dumb(&data)
+    };
+    parse_tags(refs)
+}
+
+/// Updater of resources referenced by the URL of their git repository,
+/// with the commits of the tags as their targets.
+#[derive(Debug)]
+pub struct Git;
+
+#[async_trait]
+impl Updater for Git {
+    fn name(&self) -> &str {
+        "git"
+    }
+
+    async fn get_versions(
+        &self,
+        config: &updater::Config,
+        resource: &Resource,
+    ) -> Result<Vec<VersionInfo>> {
+        get_versions(config, &resource.git_url(config)?).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+
+    use crate::support;
+    use crate::support::Response;
+
+    fn git(dir: &Path, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {args:?} failed");
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    }
+
+    /// Serve the repositories under `root` with `git http-backend` as a
+    /// CGI.
+    async fn http_backend(root: PathBuf) -> support::Mock {
+        support::serve(move |request| {
+            let output = Command::new("git")
+                .arg("http-backend")
+                .env("GIT_PROJECT_ROOT", &root)
+                .env("GIT_HTTP_EXPORT_ALL", "1")
+                .env("REQUEST_METHOD", &request.method)
+                .env("PATH_INFO", &request.path)
+                .env("QUERY_STRING", &request.query)
+                .output()
+                .unwrap();
+            let split = output
+                .stdout
+                .windows(4)
+                .position(|w| w == b"\r\n\r\n")
+                .unwrap();
+            let headers = String::from_utf8_lossy(&output.stdout[..split]).to_string();
+            let mut response = Response::new("200 OK", &output.stdout[split + 4..]);
+            for (name, value) in headers.lines().filter_map(|line| line.split_once(": ")) {
+                if name == "Status" {
+                    response.status = value.into();
+                } else {
+                    response = response.header(name, value);
+                }
+            }
+            response
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_git_get_versions() -> Result<()> {
+        if Command::new("git").arg("--version").output().is_err() {
+            return Ok(());
+        }
+        let root = tempfile::tempdir().unwrap();
+        let repo = root.path().join("checkout");
+        std::fs::create_dir_all(&repo).unwrap();
+        git(&repo, &["init", "-q"]);
+        git(&repo, &["commit", "-q", "--allow-empty", "-m", "first"]);
+        let first = git(&repo, &["rev-parse", "HEAD"]);
+        git(&repo, &["tag", "v1.0.0"]);
+        git(&repo, &["commit", "-q", "--allow-empty", "-m", "second"]);
+        let second = git(&repo, &["rev-parse", "HEAD"]);
+        git(&repo, &["tag", "-a", "-m", "v1.1.0", "v1.1.0"]);
+        let mock = http_backend(root.path().to_owned()).await;
+        let config = updater::Config {
+            user_agent: "corp-bot/1.0".into(),
+            ..Default::default()
+        };
+        let mut versions = get_versions(&config, &mock.url.join("checkout")?).await?;
+        assert_eq!(
+            mock.requests()[0].header("user-agent"),
+            Some("git/2.0 (corp-bot/1.0)")
+        );
+        versions.sort_by(|a, b| a.version.cmp(&b.version));
+        assert_eq!(
+            versions,
+            [
+                VersionInfo::new(Version::new("v1.0.0").unwrap(), Some(first)),
+                VersionInfo::new(Version::new("v1.1.0").unwrap(), Some(second)),
+            ]
+        );
+        Ok(())
+    }
+}
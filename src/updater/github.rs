@@ -2,40 +2,180 @@
 // This file is subject to the terms and conditions defined in
 // file 'LICENSE', which is part of this source code package.
 
-use reqwest::header::USER_AGENT;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::Level;
+use tracing::event;
 use tracing::instrument;
 use url::Url;
 
 use crate::error::Error;
 use crate::error::Result;
+use crate::resource::Resource;
+use crate::updater;
+use crate::updater::Fetched;
+use crate::updater::Updater;
+use crate::updater::auth::Credentials;
+use crate::updater::auth::Token;
+use crate::updater::github_app::App;
+use crate::updater::http::Validators;
 use crate::version::Version;
+use crate::version::VersionInfo;
 
-#[instrument(level = "debug")]
-async fn get_json(url: &Url) -> Result<serde_json::Value> {
-    let client = reqwest::Client::new();
-    let mut builder = client.get(url.as_str());
-    builder = builder.header(USER_AGENT, "reqwest");
-    builder = builder.header("Accept", "application/vnd.github.v3+json");
-    if let Ok(token) = std::env::var("PERSONAL_TOKEN") {
-        builder = builder.header("Authorization", format!("token {token}"));
-    }
-    let response = builder.send().await?;
+mod source;
+
+pub use source::VersionSource;
+
+/// Default github API URL, used when `GITHUB_API_URL` is not set.
+pub const API_URL: &str = "https://api.github.com";
+
+/// Configuration of the github updater.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Base URL of the github API.
+    pub api_url: Url,
+    /// Per-owner base URLs of the github API, used for owners that live
+    /// in a different github instance, indexed by lowercase owner.
+    pub api_urls: HashMap<String, Url>,
+    /// Where to get versions from, by default.
+    pub source: VersionSource,
+    /// Per-repository version sources, indexed by `owner/repo`.
+    pub sources: HashMap<String, VersionSource>,
+    /// Consider prereleases when getting versions from releases.
+    pub prereleases: bool,
+    /// Tokens used to authenticate, indexed by API host.
+    pub credentials: Credentials,
+    /// Github app used to authenticate in the instance where it's
+    /// installed, taking precedence over `credentials`.
+    pub app: Option<Arc<App>>,
+    /// Get versions in batches with the GraphQL API.
+    pub graphql: bool,
+    /// List tags with git when the API fails.
+    pub git_fallback: bool,
+}
+
+/// Get the URL of the web host of the github instance, where the git
+/// repositories are served.
+pub fn web_url(api_url: &Url) -> Result<Url> {
+    let base = updater::http::api_base(api_url);
+    if base.host_str() == Some("api.github.com") {
+        Ok(Url::parse("https://github.com/")?)
+    } else if base.path().ends_with("/api/v3/") {
+        Ok(base.join("../../")?)
+    } else {
+        Ok(base)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            api_url: Url::parse(API_URL).unwrap(),
+            api_urls: Default::default(),
+            source: Default::default(),
+            sources: Default::default(),
+            prereleases: false,
+            credentials: Default::default(),
+            app: None,
+            graphql: false,
+            git_fallback: false,
+        }
+    }
+}
+
+impl Config {
+    /// Get the base URL of the github API that hosts the resource.
+    pub fn api_url(&self, resource: &Resource) -> &Url {
+        match resource {
+            Resource::GhAction { user, .. } | Resource::GhWorkflow { user, .. } => self
+                .api_urls
+                .get(&user.to_lowercase())
+                .unwrap_or(&self.api_url),
+            Resource::Docker { .. } | Resource::Git { .. } => &self.api_url,
+        }
+    }
+
+    /// Get the version source of the resource.
+    pub fn source(&self, resource: &Resource) -> VersionSource {
+        match resource {
+            Resource::GhAction { user, repo } | Resource::GhWorkflow { user, repo, .. } => self
+                .sources
+                .get(&format!("{user}/{repo}"))
+                .copied()
+                .unwrap_or(self.source),
+            Resource::Docker { .. } | Resource::Git { .. } => self.source,
+        }
+    }
+}
+
+/// Get the token used to authenticate requests to the URL.
+#[instrument(level = "debug", skip(config))]
+pub async fn token(config: &updater::Config, url: &Url) -> Result<Option<Token>> {
+    if let Some(app) = &config.github.app
+        && app.serves(url)
+    {
+        return Ok(Some(app.token(config).await?));
+    }
+    Ok(config.github.credentials.token(url).cloned())
+}
+
+#[instrument(level = "debug", skip(config))]
+async fn get_if_modified(
+    config: &updater::Config,
+    url: &Url,
+    accept: &str,
+    validators: &Validators,
+) -> Result<Option<reqwest::Response>> {
+    let mut builder = config.client.get(url.as_str());
+    builder = builder.header("Accept", accept);
+    if let Some(token) = token(config, url).await? {
+        builder = builder.header("Authorization", format!("token {}", token.secret()));
+    }
+    let Some(response) = updater::http::send_if_modified(config, url, builder, validators).await?
+    else {
+        return Ok(None);
+    };
     if !response.status().is_success() {
         return Err(Error::HttpError(url.clone(), response.status()));
     }
-    Ok(response.json::<serde_json::Value>().await?)
+    Ok(Some(response))
+}
+
+#[instrument(level = "debug", skip(config))]
+async fn get(config: &updater::Config, url: &Url, accept: &str) -> Result<reqwest::Response> {
+    get_if_modified(config, url, accept, &Default::default())
+        .await?
+        .ok_or_else(|| Error::HttpError(url.clone(), reqwest::StatusCode::NOT_MODIFIED))
+}
+
+#[instrument(level = "debug", skip(config))]
+async fn get_json(config: &updater::Config, url: &Url) -> Result<serde_json::Value> {
+    let response = get(config, url, "application/vnd.github.v3+json").await?;
+    updater::http::json(config, url, response).await
+}
+
+/// Get the sha of the commit that the given tag points to, following
+/// annotated tags.
+#[instrument(level = "debug", skip(config))]
+async fn get_commit(config: &updater::Config, repo_url: &Url, tag: &str) -> Result<String> {
+    let url = repo_url.join(&format!("commits/refs/tags/{tag}"))?;
+    let response = get(config, &url, "application/vnd.github.sha").await?;
+    let data = updater::http::bytes(config, &url, response).await?;
+    Ok(String::from_utf8_lossy(&data).trim().to_string())
 }
 
 #[instrument(level = "debug")]
-fn parse_versions(data: serde_json::Value) -> Result<Vec<Version>> {
+fn parse_versions(data: serde_json::Value) -> Result<Vec<VersionInfo>> {
     let re_ref = regex::Regex::new(r"^refs/tags/(?P<version>.+)$").unwrap();
     data.as_array()
         .ok_or_else(|| Error::JsonParsing("invalid type for layer object list".into()))?
         .iter()
         .map(|tag_obj| {
-            tag_obj
+            let tag_obj = tag_obj
                 .as_object()
-                .ok_or_else(|| Error::JsonParsing("invalid type for tag object".into()))?
+                .ok_or_else(|| Error::JsonParsing("invalid type for tag object".into()))?;
+            let version = tag_obj
                 .get("ref")
                 .ok_or_else(|| Error::JsonParsing("ref field not found in tag object".into()))
                 .map(|ref_value| {
@@ -50,16 +190,406 @@ fn parse_versions(data: serde_json::Value) -> Result<Vec<Version>> {
                     let version_str = m.name("version").unwrap().as_str();
                     Version::new(version_str)
                         .ok_or_else(|| Error::VersionParsing(version_str.into()))
-                })?
+                })??;
+            // Annotated tags point to a tag object instead of a commit; we
+            // leave those unresolved and only follow them on demand
+            let commit = tag_obj
+                .get("object")
+                .filter(|object| object.get("type").and_then(|t| t.as_str()) == Some("commit"))
+                .and_then(|object| object.get("sha"))
+                .and_then(|sha| sha.as_str())
+                .map(String::from);
+            Ok(VersionInfo::new(version, commit))
+        })
+        .collect::<Result<Vec<VersionInfo>>>()
+}
+
+/// Parse a list of releases, skipping drafts and optionally
+/// prereleases.
+#[instrument(level = "debug")]
+fn parse_releases(data: serde_json::Value, prereleases: bool) -> Result<Vec<VersionInfo>> {
+    let releases = match data {
+        serde_json::Value::Array(releases) => releases,
+        release => vec![release],
+    };
+    releases
+        .iter()
+        .filter(|release| {
+            let flag = |name| release.get(name).and_then(|f| f.as_bool()) == Some(true);
+            !flag("draft") && (prereleases || !flag("prerelease"))
         })
-        .collect::<Result<Vec<Version>>>()
+        .map(|release| {
+            let version_str = release
+                .get("tag_name")
+                .ok_or_else(|| Error::JsonParsing("tag_name field not found in release".into()))?
+                .as_str()
+                .ok_or_else(|| {
+                    Error::JsonParsing("invalid type for tag_name field in release".into())
+                })?;
+            let version = Version::new(version_str)
+                .ok_or_else(|| Error::VersionParsing(version_str.into()))?;
+            Ok(VersionInfo::new(version, None))
+        })
+        .collect::<Result<Vec<VersionInfo>>>()
+}
+
+/// Get the versions of the resource from the API, according to its
+/// version source, unless they didn't change since the response with the
+/// given validators.
+#[instrument(level = "debug", skip(config))]
+pub async fn get_versions_if_modified(
+    config: &updater::Config,
+    resource: &Resource,
+    validators: &Validators,
+) -> Result<Fetched> {
+    let source = config.github.source(resource);
+    let url = match source {
+        VersionSource::Tags => resource.url(config)?,
+        VersionSource::Releases => resource.repo_url(config)?.join("releases?per_page=100")?,
+        VersionSource::LatestRelease => resource.repo_url(config)?.join("releases/latest")?,
+        VersionSource::Git => {
+            let versions = updater::git::get_versions(config, &resource.git_url(config)?).await?;
+            return Ok(Fetched::Modified(versions, Default::default()));
+        }
+    };
+    let Some(response) =
+        get_if_modified(config, &url, "application/vnd.github.v3+json", validators).await?
+    else {
+        return Ok(Fetched::NotModified);
+    };
+    let validators = Validators::from_response(&response);
+    let next = next_link(response.headers());
+    let mut data = updater::http::json(config, &url, response).await?;
+    // Releases come in pages of 100
+    if let (Some(next), serde_json::Value::Array(releases)) = (next, &mut data) {
+        releases.extend(get_all_json(config, &next).await?);
+    }
+    let versions = match source {
+        VersionSource::Releases => parse_releases(data, config.github.prereleases)?,
+        VersionSource::LatestRelease => parse_releases(data, false)?,
+        _ => parse_versions(data)?,
+    };
+    Ok(Fetched::Modified(versions, validators))
+}
+
+/// Updater of github actions and reusable workflows, that gets the
+/// versions from the configured source.
+#[derive(Debug)]
+pub struct Github;
+
+#[async_trait]
+impl Updater for Github {
+    fn name(&self) -> &str {
+        "github"
+    }
+
+    fn graphql_batchable(&self) -> bool {
+        true
+    }
+
+    async fn get_versions(
+        &self,
+        config: &updater::Config,
+        resource: &Resource,
+    ) -> Result<Vec<VersionInfo>> {
+        let validators = Validators::default();
+        let fetched = self
+            .get_versions_if_modified(config, resource, &validators)
+            .await?;
+        Ok(fetched.into_versions().unwrap_or_default())
+    }
+
+    async fn get_versions_if_modified(
+        &self,
+        config: &updater::Config,
+        resource: &Resource,
+        validators: &Validators,
+    ) -> Result<Fetched> {
+        match get_versions_if_modified(config, resource, validators).await {
+            Err(e)
+                if config.github.git_fallback
+                    && config.github.source(resource) != VersionSource::Git =>
+            {
+                event!(
+                    Level::WARN,
+                    resource = %resource,
+                    error = %e,
+                    "error getting versions from the github API, falling back to git"
+                );
+                let versions =
+                    updater::git::get_versions(config, &resource.git_url(config)?).await?;
+                Ok(Fetched::Modified(versions, Default::default()))
+            }
+            fetched => fetched,
+        }
+    }
+
+    async fn resolve(
+        &self,
+        config: &updater::Config,
+        resource: &Resource,
+        versions: &[VersionInfo],
+        version: &Version,
+    ) -> Result<Option<String>> {
+        resolve(config, &resource.repo_url(config)?, versions, version).await
+    }
 }
 
+/// Return the numeric components of a floating `vMAJOR` or
+/// `vMAJOR.MINOR` tag, or `None` if the version is not floating.
 #[instrument(level = "debug")]
-pub async fn get_versions(url: &Url) -> Result<Vec<Version>> {
-    let data = get_json(url).await?;
-    let versions = parse_versions(data)?;
-    Ok(versions)
+pub fn floating_components(version: &Version) -> Option<Vec<u64>> {
+    let s = version.string.strip_prefix('v').unwrap_or(&version.string);
+    let components = s
+        .split('.')
+        .map(|c| c.parse::<u64>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    (components.len() <= 2).then_some(components)
+}
+
+/// Find the newest full release that the `floating` tag should point to.
+#[instrument(level = "debug")]
+pub fn newest_matching<'a>(
+    versions: &'a [VersionInfo],
+    floating: &Version,
+) -> Option<&'a VersionInfo> {
+    let components = floating_components(floating)?;
+    versions
+        .iter()
+        .filter(|info| floating_components(&info.version).is_none())
+        .filter(|info| {
+            info.version.version.as_ref().is_some_and(|v| {
+                v.pre.is_empty()
+                    && components[0] == v.major
+                    && components.get(1).is_none_or(|minor| *minor == v.minor)
+            })
+        })
+        .max_by(|a, b| a.version.cmp(&b.version))
+}
+
+/// Get the sha of the commit that the given version of the repository
+/// resolves to, or `None` if the version is not a tag.
+#[instrument(level = "debug", skip(config))]
+pub async fn resolve(
+    config: &updater::Config,
+    repo_url: &Url,
+    versions: &[VersionInfo],
+    version: &Version,
+) -> Result<Option<String>> {
+    if is_sha(version) {
+        return Ok(Some(version.string.clone()));
+    }
+    let Some(info) = versions.iter().find(|info| &info.version == version) else {
+        return Ok(None);
+    };
+    match &info.target {
+        Some(commit) => Ok(Some(commit.clone())),
+        None => Ok(Some(get_commit(config, repo_url, &version.string).await?)),
+    }
+}
+
+/// Check if the version is a full commit sha.
+pub fn is_sha(version: &Version) -> bool {
+    version.string.len() == 40 && version.string.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Maximum number of branches and tags that the commit is compared with
+/// before giving up on finding out if it's reachable.
+const MAX_COMPARES: usize = 20;
+
+/// Get the URL of the next page of a paginated response, from its `Link`
+/// header.
+fn next_link(headers: &reqwest::header::HeaderMap) -> Option<Url> {
+    let link = headers.get(reqwest::header::LINK)?.to_str().ok()?;
+    link.split(',').find_map(|part| {
+        let (url, params) = part.split_once(';')?;
+        params
+            .split(';')
+            .any(|param| param.trim() == r#"rel="next""#)
+            .then(|| Url::parse(url.trim().trim_start_matches('<').trim_end_matches('>')).ok())?
+    })
+}
+
+/// Get all the items of a paginated list.
+#[instrument(level = "debug", skip(config))]
+async fn get_all_json(config: &updater::Config, url: &Url) -> Result<Vec<serde_json::Value>> {
+    let mut items = vec![];
+    let mut next = Some(url.clone());
+    while let Some(url) = next {
+        let response = get(config, &url, "application/vnd.github.v3+json").await?;
+        next = next_link(response.headers());
+        match updater::http::json(config, &url, response).await? {
+            serde_json::Value::Array(page) => items.extend(page),
+            _ => return Err(Error::JsonParsing("invalid type for list".into())),
+        }
+    }
+    Ok(items)
+}
+
+/// Compare the commit with the base, returning if it's reachable from it,
+/// or `None` if the base or the commit were not found.
+#[instrument(level = "debug", skip(config))]
+async fn is_reachable_from(
+    config: &updater::Config,
+    repo_url: &Url,
+    base: &str,
+    commit: &str,
+) -> Result<Option<bool>> {
+    let url = repo_url.join(&format!("compare/{base}...{commit}"))?;
+    let data = match get_json(config, &url).await {
+        Ok(data) => data,
+        Err(Error::HttpError(_, reqwest::StatusCode::NOT_FOUND)) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let status = data
+        .get("status")
+        .and_then(|status| status.as_str())
+        .ok_or_else(|| Error::JsonParsing("status field not found in comparison".into()))?;
+    Ok(Some(status == "behind" || status == "identical"))
+}
+
+/// Check if the commit is reachable from one of the repository's own
+/// branches or tags, starting with the default branch.
+///
+/// Commits that only exist in forks are still served through the
+/// upstream repository, so a SHA pin alone doesn't guarantee that the
+/// code came from upstream.
+///
+/// Returns `None` if that can't be determined: when a comparison is not
+/// found, or after comparing with [`MAX_COMPARES`] branches and tags.
+#[instrument(level = "debug", skip(config))]
+pub async fn is_reachable(
+    config: &updater::Config,
+    repo_url: &Url,
+    versions: &[VersionInfo],
+    commit: &str,
+) -> Result<Option<bool>> {
+    if versions
+        .iter()
+        .any(|info| info.target.as_deref() == Some(commit))
+    {
+        return Ok(Some(true));
+    }
+    // The repository endpoint doesn't take the trailing slash
+    let repo = Url::parse(repo_url.as_str().trim_end_matches('/'))?;
+    let default_branch = get_json(config, &repo)
+        .await?
+        .get("default_branch")
+        .and_then(|branch| branch.as_str())
+        .map(String::from)
+        .ok_or_else(|| Error::JsonParsing("default_branch field not found in repository".into()))?;
+    let mut known = true;
+    match is_reachable_from(config, repo_url, &default_branch, commit).await? {
+        Some(true) => return Ok(Some(true)),
+        Some(false) => {}
+        None => known = false,
+    }
+    let branches = parse_branches(serde_json::Value::Array(
+        get_all_json(config, &repo_url.join("branches?per_page=100")?).await?,
+    ))?;
+    if branches.iter().any(|(_, head)| head == commit) {
+        return Ok(Some(true));
+    }
+    let mut tags = versions
+        .iter()
+        .map(|info| &info.version)
+        .collect::<Vec<_>>();
+    tags.sort();
+    let mut bases = branches
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .filter(|name| *name != default_branch)
+        .chain(tags.iter().rev().map(|version| version.string.as_str()));
+    for base in bases.by_ref().take(MAX_COMPARES) {
+        match is_reachable_from(config, repo_url, base, commit).await? {
+            Some(true) => return Ok(Some(true)),
+            Some(false) => {}
+            None => known = false,
+        }
+    }
+    if bases.next().is_some() {
+        event!(
+            Level::WARN,
+            repo = %repo_url,
+            commit = %commit,
+            "too many branches and tags to check if the commit is reachable"
+        );
+        known = false;
+    }
+    Ok(known.then_some(false))
+}
+
+/// Check if the repository has a branch with the given name.
+#[instrument(level = "debug", skip(config))]
+pub async fn is_branch(config: &updater::Config, repo_url: &Url, name: &str) -> Result<bool> {
+    // This lists the branches that start with the name
+    let refs = get_json(
+        config,
+        &repo_url.join(&format!("git/matching-refs/heads/{name}"))?,
+    )
+    .await?;
+    let full = format!("refs/heads/{name}");
+    Ok(refs
+        .as_array()
+        .ok_or_else(|| Error::JsonParsing("invalid type for ref list".into()))?
+        .iter()
+        .any(|git_ref| git_ref.get("ref").and_then(|r| r.as_str()) == Some(full.as_str())))
+}
+
+/// Parse the list of branches into names and head commits.
+#[instrument(level = "debug")]
+fn parse_branches(data: serde_json::Value) -> Result<Vec<(String, String)>> {
+    data.as_array()
+        .ok_or_else(|| Error::JsonParsing("invalid type for branch list".into()))?
+        .iter()
+        .map(|branch| {
+            let name = branch
+                .get("name")
+                .and_then(|name| name.as_str())
+                .ok_or_else(|| Error::JsonParsing("name field not found in branch".into()))?;
+            let sha = branch
+                .get("commit")
+                .and_then(|commit| commit.get("sha"))
+                .and_then(|sha| sha.as_str())
+                .ok_or_else(|| Error::JsonParsing("commit sha not found in branch".into()))?;
+            Ok((name.to_string(), sha.to_string()))
+        })
+        .collect()
+}
+
+/// Check if the `floating` tag points to the same commit as the newest
+/// full release that it matches.
+///
+/// The floating tag is looked up directly when it's not among the
+/// versions, as is the case with the release sources; the check is
+/// skipped if the tag doesn't exist.
+///
+/// Returns the newest release if the floating tag is stale.
+#[instrument(level = "debug", skip(config))]
+pub async fn stale_floating(
+    config: &updater::Config,
+    repo_url: &Url,
+    versions: &[VersionInfo],
+    floating: &Version,
+) -> Result<Option<Version>> {
+    let Some(newest) = newest_matching(versions, floating) else {
+        return Ok(None);
+    };
+    let floating_commit = if versions.iter().any(|info| &info.version == floating) {
+        resolve(config, repo_url, versions, floating).await?
+    } else {
+        match get_commit(config, repo_url, &floating.string).await {
+            Ok(commit) => Some(commit),
+            Err(Error::HttpError(_, reqwest::StatusCode::NOT_FOUND)) => return Ok(None),
+            Err(e) => return Err(e),
+        }
+    };
+    let newest_commit = resolve(config, repo_url, versions, &newest.version).await?;
+    if floating_commit == newest_commit {
+        Ok(None)
+    } else {
+        Ok(Some(newest.version.clone()))
+    }
 }
 
 #[test]
@@ -109,7 +639,10 @@ fn test_docker_parse_versions() -> Result<()> {
 ]
 "#;
     let json_value: serde_json::Value = serde_json::from_str(json_str)?;
-    let mut versions = parse_versions(json_value)?.into_iter().collect::<Vec<_>>();
+    let mut versions = parse_versions(json_value)?
+        .into_iter()
+        .map(|info| info.version)
+        .collect::<Vec<_>>();
     versions.sort();
     let versions = versions
         .into_iter()
@@ -118,3 +651,166 @@ fn test_docker_parse_versions() -> Result<()> {
     assert_eq!(versions, ["latest", "v0.1", "0.2", "v0.4"]);
     Ok(())
 }
+
+#[test]
+fn test_github_newest_matching() -> Result<()> {
+    let versions = [
+        "v2",
+        "v2.9.0",
+        "v3",
+        "v3.1",
+        "v3.1.0",
+        "v3.4.2",
+        "v3.5.0-rc1",
+        "v4.0.0",
+    ]
+    .into_iter()
+    .map(|v| VersionInfo::new(Version::new(v).unwrap(), None))
+    .collect::<Vec<_>>();
+    let newest = |floating: &str| {
+        newest_matching(&versions, &Version::new(floating).unwrap())
+            .map(|info| info.version.string.clone())
+    };
+    assert_eq!(newest("v3"), Some("v3.4.2".into()));
+    assert_eq!(newest("v3.1"), Some("v3.1.0".into()));
+    assert_eq!(newest("v2"), Some("v2.9.0".into()));
+    assert_eq!(newest("v5"), None);
+    assert_eq!(newest("v3.4.2"), None);
+    Ok(())
+}
+
+#[test]
+fn test_github_next_link() {
+    let mut headers = reqwest::header::HeaderMap::new();
+    assert_eq!(next_link(&headers), None);
+    headers.insert(
+        reqwest::header::LINK,
+        r#"<https://api.github.com/repositories/1/branches?page=3>; rel="next", <https://api.github.com/repositories/1/branches?page=5>; rel="last""#
+            .parse()
+            .unwrap(),
+    );
+    assert_eq!(
+        next_link(&headers).map(String::from),
+        Some("https://api.github.com/repositories/1/branches?page=3".to_string())
+    );
+}
+
+#[test]
+fn test_github_parse_branches() -> Result<()> {
+    let json_str = r#"
+[
+  {
+    "name": "main",
+    "commit": {
+      "sha": "c5a7806660adbe173f04e3e038b0ccdcd758773c",
+      "url": "https://api.github.com/repos/lpenz/ghworkflow-rust/commits/c5a7806660adbe173f04e3e038b0ccdcd758773c"
+    },
+    "protected": true
+  }
+]
+"#;
+    let json_value: serde_json::Value = serde_json::from_str(json_str)?;
+    assert_eq!(
+        parse_branches(json_value)?,
+        [(
+            "main".to_string(),
+            "c5a7806660adbe173f04e3e038b0ccdcd758773c".to_string()
+        )]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_github_parse_releases() -> Result<()> {
+    let json_str = r#"
+[
+  {"tag_name": "v3.0.0-rc1", "draft": false, "prerelease": true},
+  {"tag_name": "v2.1.0", "draft": true, "prerelease": false},
+  {"tag_name": "v2.0.0", "draft": false, "prerelease": false}
+]
+"#;
+    let json_value: serde_json::Value = serde_json::from_str(json_str)?;
+    let versions = |prereleases| -> Result<Vec<String>> {
+        Ok(parse_releases(json_value.clone(), prereleases)?
+            .into_iter()
+            .map(|info| info.version.string)
+            .collect())
+    };
+    assert_eq!(versions(false)?, ["v2.0.0"]);
+    assert_eq!(versions(true)?, ["v3.0.0-rc1", "v2.0.0"]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_github_stale_floating_releases() -> Result<()> {
+    use crate::support;
+    use crate::support::Response;
+
+    let mock = support::serve(|request| match request.path.as_str() {
+        "/repos/actions/checkout/releases" if request.query == "page=2" => {
+            Response::json("200 OK", r#"[{"tag_name": "v3.6.0"}]"#)
+        }
+        "/repos/actions/checkout/releases" => {
+            let next = format!(
+                "<http://{}/repos/actions/checkout/releases?page=2>; rel=\"next\"",
+                request.header("host").unwrap()
+            );
+            Response::json(
+                "200 OK",
+                r#"[{"tag_name": "v4.2.0"}, {"tag_name": "v4.1.0"}]"#,
+            )
+            .header("Link", &next)
+        }
+        "/repos/actions/checkout/commits/refs/tags/v4" => Response::new("200 OK", "aaa"),
+        "/repos/actions/checkout/commits/refs/tags/v4.2.0" => Response::new("200 OK", "bbb"),
+        "/repos/actions/checkout/commits/refs/tags/v3" => Response::new("200 OK", "ccc"),
+        "/repos/actions/checkout/commits/refs/tags/v3.6.0" => Response::new("200 OK", "ccc"),
+        _ => Response::not_found(),
+    })
+    .await;
+    let mut config = updater::Config::default();
+    config.github.api_url = mock.url.clone();
+    config.github.source = VersionSource::Releases;
+    let checkout = Resource::new_ghaction("actions".into(), "checkout".into());
+    let repo_url = checkout.repo_url(&config)?;
+    let versions = Github.get_versions(&config, &checkout).await?;
+    assert_eq!(versions.len(), 3);
+    let stale = |floating: &str| {
+        let floating = Version::new(floating).unwrap();
+        let config = &config;
+        let repo_url = &repo_url;
+        let versions = &versions;
+        async move { stale_floating(config, repo_url, versions, &floating).await }
+    };
+    assert_eq!(stale("v4").await?, Version::new("v4.2.0"));
+    assert_eq!(stale("v3").await?, None);
+    // v4.1 is not a tag
+    assert_eq!(stale("v4.1").await?, None);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_github_is_branch() -> Result<()> {
+    use crate::support;
+    use crate::support::Response;
+
+    let mock = support::serve(|request| match request.path.as_str() {
+        "/repos/lpenz/ghaction-cmake/git/matching-refs/heads/main" => Response::json(
+            "200 OK",
+            r#"[{"ref": "refs/heads/main"}, {"ref": "refs/heads/main-old"}]"#,
+        ),
+        "/repos/lpenz/ghaction-cmake/git/matching-refs/heads/mai" => {
+            Response::json("200 OK", r#"[{"ref": "refs/heads/main"}]"#)
+        }
+        _ => Response::json("200 OK", "[]"),
+    })
+    .await;
+    let mut config = updater::Config::default();
+    config.github.api_url = mock.url.clone();
+    let cmake = Resource::new_ghaction("lpenz".into(), "ghaction-cmake".into());
+    let repo_url = cmake.repo_url(&config)?;
+    assert!(is_branch(&config, &repo_url, "main").await?);
+    assert!(!is_branch(&config, &repo_url, "mai").await?);
+    assert!(!is_branch(&config, &repo_url, "latest").await?);
+    Ok(())
+}
@@ -0,0 +1,22 @@
+// Copyright (C) 2022 Leandro Lisboa Penz <lpenz@lpenz.org>
+// This file is subject to the terms and conditions defined in
+// file 'LICENSE', which is part of this source code package.
+
+// Also included by build.rs, through src/cli.rs, so it can only use clap
+// and std, and can't have inner attributes.
+
+use clap::ValueEnum;
+
+/// Where the versions of github resources are taken from.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Default, Debug)]
+pub enum VersionSource {
+    /// Use all tags of the repository
+    #[default]
+    Tags,
+    /// Use the tags of published releases, excluding drafts
+    Releases,
+    /// Use only the release marked as latest
+    LatestRelease,
+    /// Use the tags listed by the git server, like `git ls-remote` does
+    Git,
+}
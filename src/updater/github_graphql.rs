@@ -0,0 +1,465 @@
+// Copyright (C) 2022 Leandro Lisboa Penz <lpenz@lpenz.org>
+// This file is subject to the terms and conditions defined in
+// file 'LICENSE', which is part of this source code package.
+
+//! Batched lookups of the versions of github resources with the GraphQL
+//! API.
+//!
+//! Each resource of a batch becomes an aliased `repository` field of a
+//! single query, so that a whole batch costs one request. The GraphQL API
+//! only works with authentication; resources of hosts without a token
+//! fall back to the REST API, as do the repositories with more tags or
+//! releases than fit in the first page and the batches whose query fails.
+
+use futures::future::join_all;
+use reqwest::header::CONTENT_TYPE;
+use serde_json::Value;
+use std::collections::HashMap;
+use tracing::Level;
+use tracing::event;
+use tracing::instrument;
+use url::Url;
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::resource::Resource;
+use crate::updater;
+use crate::updater::github;
+use crate::updater::github::VersionSource;
+use crate::version::Version;
+use crate::version::VersionInfo;
+
+/// Maximum number of resources looked up in a single query.
+pub const BATCH_SIZE: usize = 50;
+
+/// Check if the versions of the resource are looked up in batches.
+pub fn batched(config: &updater::Config, resource: &Resource) -> bool {
+    config.github.graphql
+        && resource.is_github()
+        && config.github.source(resource) != VersionSource::Git
+        && resource
+            .updater(config)
+            .is_ok_and(|updater| updater.graphql_batchable())
+}
+
+/// Get the URL of the GraphQL API of the github instance.
+///
+/// Enterprise servers have the REST API under `/api/v3` and the GraphQL
+/// one under `/api/graphql`.
+pub fn graphql_url(api_url: &Url) -> Result<Url> {
+    let base = updater::http::api_base(api_url);
+    if base.path().ends_with("/api/v3/") {
+        Ok(base.join("../graphql")?)
+    } else {
+        Ok(base.join("graphql")?)
+    }
+}
+
+/// Fields that we query, according to the version source.
+///
+/// Releases are queried without the commit of their tag, which the REST
+/// API doesn't have either, so that both APIs return the same versions.
+fn fields(source: VersionSource) -> &'static str {
+    match source {
+        VersionSource::Tags | VersionSource::Git => {
+            "refs(refPrefix: \"refs/tags/\", first: 100, \
+             orderBy: {field: TAG_COMMIT_DATE, direction: DESC}) { \
+             pageInfo { hasNextPage } \
+             nodes { name target { __typename oid ... on Tag { target { __typename oid } } } } }"
+        }
+        VersionSource::Releases => {
+            "releases(first: 100, orderBy: {field: CREATED_AT, direction: DESC}) { \
+             pageInfo { hasNextPage } nodes { tagName isDraft isPrerelease } }"
+        }
+        VersionSource::LatestRelease => "latestRelease { tagName isDraft isPrerelease }",
+    }
+}
+
+/// Build the query that gets the versions of all the resources, with the
+/// one at index `i` aliased as `r{i}`.
+fn query(config: &updater::Config, resources: &[Resource]) -> Result<String> {
+    let repositories = resources
+        .iter()
+        .enumerate()
+        .map(|(i, resource)| {
+            let (user, repo) = match resource {
+                Resource::GhAction { user, repo } | Resource::GhWorkflow { user, repo, .. } => {
+                    (user, repo)
+                }
+                Resource::Docker { .. } | Resource::Git { .. } => {
+                    return Err(Error::NotGithub(resource.to_string()));
+                }
+            };
+            Ok(format!(
+                "r{i}: repository(owner: {}, name: {}) {{ {} }}",
+                Value::from(user.as_str()),
+                Value::from(repo.as_str()),
+                fields(config.github.source(resource))
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(format!("query {{ {} }}", repositories.join(" ")))
+}
+
+fn parse_version(version_str: &str, target: Option<&str>) -> Result<VersionInfo> {
+    let version =
+        Version::new(version_str).ok_or_else(|| Error::VersionParsing(version_str.into()))?;
+    Ok(VersionInfo::new(version, target.map(String::from)))
+}
+
+/// Parse the tags of a repository, following annotated tags to the
+/// commit they point to.
+fn parse_refs(refs: &Value) -> Result<Vec<VersionInfo>> {
+    refs.get("nodes")
+        .and_then(|nodes| nodes.as_array())
+        .ok_or_else(|| Error::JsonParsing("nodes not found in refs".into()))?
+        .iter()
+        .map(|node| {
+            let name = node
+                .get("name")
+                .and_then(|name| name.as_str())
+                .ok_or_else(|| Error::JsonParsing("name not found in ref".into()))?;
+            let mut target = node.get("target");
+            if target.and_then(|t| t.get("__typename")) == Some(&Value::from("Tag")) {
+                target = target.and_then(|t| t.get("target"));
+            }
+            let commit = target
+                .filter(|t| t.get("__typename") == Some(&Value::from("Commit")))
+                .and_then(|t| t.get("oid"))
+                .and_then(|oid| oid.as_str());
+            parse_version(name, commit)
+        })
+        .collect()
+}
+
+/// Parse releases, skipping drafts and optionally prereleases.
+fn parse_releases(releases: &[&Value], prereleases: bool) -> Result<Vec<VersionInfo>> {
+    releases
+        .iter()
+        .filter(|release| {
+            let flag = |name| release.get(name).and_then(|f| f.as_bool()) == Some(true);
+            !flag("isDraft") && (prereleases || !flag("isPrerelease"))
+        })
+        .map(|release| {
+            let name = release
+                .get("tagName")
+                .and_then(|name| name.as_str())
+                .ok_or_else(|| Error::JsonParsing("tagName not found in release".into()))?;
+            parse_version(name, None)
+        })
+        .collect()
+}
+
+/// Check if the list has more pages than the one we got.
+fn has_next_page(connection: Option<&Value>) -> bool {
+    connection
+        .and_then(|connection| connection.get("pageInfo"))
+        .and_then(|page_info| page_info.get("hasNextPage"))
+        .and_then(|next| next.as_bool())
+        == Some(true)
+}
+
+/// Parse the versions of the repository, or return `None` if they don't
+/// fit in a single page.
+fn parse_repository(
+    config: &updater::Config,
+    resource: &Resource,
+    repository: &Value,
+) -> Result<Option<Vec<VersionInfo>>> {
+    let source = config.github.source(resource);
+    let connection = match source {
+        VersionSource::Tags | VersionSource::Git => repository.get("refs"),
+        VersionSource::Releases => repository.get("releases"),
+        VersionSource::LatestRelease => None,
+    };
+    if has_next_page(connection) {
+        return Ok(None);
+    }
+    let versions = match source {
+        VersionSource::Tags | VersionSource::Git => parse_refs(
+            connection.ok_or_else(|| Error::JsonParsing("refs not found in repository".into()))?,
+        ),
+        VersionSource::Releases => parse_releases(
+            &connection
+                .and_then(|releases| releases.get("nodes"))
+                .and_then(|nodes| nodes.as_array())
+                .ok_or_else(|| Error::JsonParsing("releases not found in repository".into()))?
+                .iter()
+                .collect::<Vec<_>>(),
+            config.github.prereleases,
+        ),
+        VersionSource::LatestRelease => parse_releases(
+            &repository
+                .get("latestRelease")
+                .filter(|release| !release.is_null())
+                .into_iter()
+                .collect::<Vec<_>>(),
+            false,
+        ),
+    };
+    versions.map(Some)
+}
+
+/// Split the query response into the results of each resource, with
+/// `None` for the resources whose versions don't fit in a single page.
+fn parse_response(
+    config: &updater::Config,
+    resources: &[Resource],
+    data: &Value,
+) -> Vec<Option<Result<Vec<VersionInfo>>>> {
+    // Errors of a specific repository have its alias as the path
+    let mut errors = HashMap::<&str, &str>::new();
+    for error in data
+        .get("errors")
+        .and_then(|errors| errors.as_array())
+        .into_iter()
+        .flatten()
+    {
+        let path = error
+            .get("path")
+            .and_then(|path| path.get(0))
+            .and_then(|path| path.as_str())
+            .unwrap_or_default();
+        let message = error
+            .get("message")
+            .and_then(|message| message.as_str())
+            .unwrap_or("unknown error");
+        errors.entry(path).or_insert(message);
+    }
+    resources
+        .iter()
+        .enumerate()
+        .map(|(i, resource)| {
+            let alias = format!("r{i}");
+            match data.get("data").and_then(|d| d.get(&alias)) {
+                Some(repository) if !repository.is_null() => {
+                    parse_repository(config, resource, repository).transpose()
+                }
+                _ => Some(Err(Error::Graphql(
+                    errors
+                        .get(alias.as_str())
+                        .or_else(|| errors.get(""))
+                        .unwrap_or(&"repository not found")
+                        .to_string(),
+                ))),
+            }
+        })
+        .collect()
+}
+
+/// Look up the versions of all the resources, which must be served by
+/// the same github instance, in a single query.
+#[instrument(level = "debug", skip(config))]
+async fn get_batch(
+    config: &updater::Config,
+    url: &Url,
+    resources: &[Resource],
+) -> Result<Vec<Result<Vec<VersionInfo>>>> {
+    let Some(token) = github::token(config, url).await? else {
+        event!(
+            Level::INFO,
+            url = %url,
+            "no token for the GraphQL API, falling back to REST"
+        );
+        return Ok(join_all(resources.iter().map(|r| r.get_versions(config))).await);
+    };
+    let body = serde_json::json!({ "query": query(config, resources)? });
+    let request = config
+        .client
+        .post(url.as_str())
+        .header(CONTENT_TYPE, "application/json")
+        .header("Authorization", format!("bearer {}", token.secret()))
+        .body(body.to_string());
+    let response = updater::http::send(config, url, request).await?;
+    if !response.status().is_success() {
+        return Err(Error::HttpError(url.clone(), response.status()));
+    }
+    let data = updater::http::json(config, url, response).await?;
+    let results = parse_response(config, resources, &data);
+    Ok(join_all(
+        resources
+            .iter()
+            .zip(results)
+            .map(|(resource, versions)| async move {
+                match versions {
+                    Some(versions) => versions,
+                    None => {
+                        event!(
+                            Level::INFO,
+                            resource = %resource,
+                            "versions don't fit in a GraphQL page, falling back to REST"
+                        );
+                        resource.get_versions(config).await
+                    }
+                }
+            }),
+    )
+    .await)
+}
+
+/// Get the versions of the github resources, with one query per github
+/// instance.
+#[instrument(level = "debug", skip(config))]
+pub async fn get_versions(
+    config: &updater::Config,
+    resources: Vec<Resource>,
+) -> Vec<(Resource, Result<Vec<VersionInfo>>)> {
+    let mut batches = HashMap::<Url, Vec<Resource>>::new();
+    let mut results = vec![];
+    for resource in resources {
+        match graphql_url(config.github.api_url(&resource)) {
+            Ok(url) => batches.entry(url).or_default().push(resource),
+            Err(e) => results.push((resource, Err(e))),
+        }
+    }
+    let batches = batches.into_iter().map(|(url, resources)| async move {
+        let versions = match get_batch(config, &url, &resources).await {
+            Ok(versions) => versions,
+            Err(e) => {
+                event!(
+                    Level::WARN,
+                    url = %url,
+                    error = %e,
+                    "GraphQL query failed, falling back to REST"
+                );
+                join_all(resources.iter().map(|r| r.get_versions(config))).await
+            }
+        };
+        resources.into_iter().zip(versions).collect::<Vec<_>>()
+    });
+    results.extend(join_all(batches).await.into_iter().flatten());
+    results
+}
+
+#[test]
+fn test_github_graphql() -> Result<()> {
+    let mut config = updater::Config::default();
+    config
+        .github
+        .sources
+        .insert("lpenz/ghaction-cmake".into(), VersionSource::LatestRelease);
+    let resources = [
+        Resource::new_ghaction("actions".into(), "checkout".into()),
+        Resource::new_ghaction("lpenz".into(), "ghaction-cmake".into()),
+        Resource::new_ghaction("lpenz".into(), "gone".into()),
+        Resource::new_ghaction("lpenz".into(), "many".into()),
+    ];
+    let q = query(&config, &resources)?;
+    assert!(q.contains(r#"r0: repository(owner: "actions", name: "checkout") { refs("#));
+    assert!(
+        q.contains(r#"r1: repository(owner: "lpenz", name: "ghaction-cmake") { latestRelease"#)
+    );
+    let data: Value = serde_json::from_str(
+        r#"{
+  "data": {
+    "r0": {"refs": {"pageInfo": {"hasNextPage": false}, "nodes": [
+      {"name": "v4.1.0", "target": {"__typename": "Commit", "oid": "aaa"}},
+      {"name": "v4", "target": {"__typename": "Tag", "oid": "ttt", "target": {"__typename": "Commit", "oid": "bbb"}}}
+    ]}},
+    "r1": {"latestRelease": {"tagName": "v0.9", "isDraft": false, "isPrerelease": false}},
+    "r2": null,
+    "r3": {"refs": {"pageInfo": {"hasNextPage": true}, "nodes": []}}
+  },
+  "errors": [{"type": "NOT_FOUND", "path": ["r2"], "message": "Could not resolve to a Repository with the name 'lpenz/gone'."}]
+}"#,
+    )?;
+    let results = parse_response(&config, &resources, &data);
+    let checkout = results[0].as_ref().unwrap().as_ref().unwrap();
+    assert_eq!(checkout[0], parse_version("v4.1.0", Some("aaa"))?);
+    assert_eq!(checkout[1], parse_version("v4", Some("bbb"))?);
+    assert_eq!(
+        results[1].as_ref().unwrap().as_ref().unwrap(),
+        &[parse_version("v0.9", None)?]
+    );
+    assert!(
+        results[2]
+            .as_ref()
+            .unwrap()
+            .as_ref()
+            .unwrap_err()
+            .to_string()
+            .contains("lpenz/gone")
+    );
+    // Falls back to REST
+    assert!(results[3].is_none());
+    assert_eq!(
+        graphql_url(&Url::parse("https://ghes.example.com/api/v3")?)?.as_str(),
+        "https://ghes.example.com/api/graphql"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_github_graphql_batched() {
+    use std::sync::Arc;
+
+    /// A plugin that happens to have the same name as the builtin updater.
+    #[derive(Debug)]
+    struct Impostor;
+
+    #[async_trait::async_trait]
+    impl updater::Updater for Impostor {
+        fn name(&self) -> &str {
+            "github"
+        }
+
+        async fn get_versions(
+            &self,
+            _config: &updater::Config,
+            _resource: &Resource,
+        ) -> Result<Vec<VersionInfo>> {
+            Ok(vec![])
+        }
+    }
+
+    let mut config = updater::Config::default();
+    config.github.graphql = true;
+    let checkout = Resource::new_ghaction("actions".into(), "checkout".into());
+    let cmake = Resource::new_ghaction("lpenz".into(), "ghaction-cmake".into());
+    config
+        .registry
+        .register_pattern("lpenz/*", Arc::new(Impostor));
+    assert!(batched(&config, &checkout));
+    assert!(!batched(&config, &cmake));
+}
+
+#[tokio::test]
+async fn test_github_graphql_fallback() -> Result<()> {
+    use crate::support;
+    use crate::support::Response;
+    use crate::updater::auth::Token;
+
+    let mock = support::serve(|request| match request.path.as_str() {
+        "/graphql" => Response::new("401 Unauthorized", "bad credentials"),
+        "/repos/actions/checkout/git/matching-refs/tags" => Response::json(
+            "200 OK",
+            r#"[{"ref": "refs/tags/v4", "object": {"sha": "aaa", "type": "commit"}}]"#,
+        ),
+        _ => Response::not_found(),
+    })
+    .await;
+    let mut config = updater::Config::default();
+    config.github.api_url = mock.url.clone();
+    config.github.graphql = true;
+    config
+        .github
+        .credentials
+        .insert(mock.url.host_str().unwrap(), Token::new("ghp_test"));
+    let checkout = Resource::new_ghaction("actions".into(), "checkout".into());
+    let results = get_versions(&config, vec![checkout.clone()]).await;
+    // The failed query falls back to REST
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].0, checkout);
+    let versions = results[0].1.as_ref().unwrap();
+    assert_eq!(versions[0].version, Version::new("v4").unwrap());
+    let paths = mock
+        .requests()
+        .into_iter()
+        .map(|request| request.path)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        paths,
+        ["/graphql", "/repos/actions/checkout/git/matching-refs/tags"]
+    );
+    Ok(())
+}
@@ -0,0 +1,341 @@
+// Copyright (C) 2022 Leandro Lisboa Penz <lpenz@lpenz.org>
+// This file is subject to the terms and conditions defined in
+// file 'LICENSE', which is part of this source code package.
+
+//! The shared HTTP client, and requests that are retried on rate limits
+//! and transient errors.
+//!
+//! Rate limited responses are retried after the delay the server asks for
+//! in `Retry-After` or `X-RateLimit-Reset`, when present; everything else
+//! uses jittered exponential backoff. We give up when the
+//! [`RetryPolicy`] runs out of retries or time.
+//!
+//! Requests, retries and the bytes of the bodies read with [`bytes`] or
+//! [`json`] are recorded in the [`Stats`](crate::stats::Stats) of the
+//! updaters.
+
+use reqwest::StatusCode;
+use reqwest::header::HeaderMap;
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::Level;
+use tracing::event;
+use tracing::instrument;
+use url::Url;
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::updater;
+
+/// Default User-Agent of our requests.
+pub const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// Options of the HTTP client shared by all updaters.
+#[derive(Debug, Clone)]
+pub struct ClientOptions {
+    pub user_agent: String,
+    /// Maximum time to establish a connection.
+    pub connect_timeout: Duration,
+    /// Maximum time between reads of a response.
+    pub read_timeout: Duration,
+    /// Proxy for all requests; by default the `HTTP_PROXY`, `HTTPS_PROXY`
+    /// and `NO_PROXY` environment variables are used.
+    pub proxy: Option<String>,
+    /// PEM bundle with root certificates trusted in addition to the
+    /// builtin ones.
+    pub ca_certs: Option<Vec<u8>>,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        ClientOptions {
+            user_agent: USER_AGENT.into(),
+            connect_timeout: Duration::from_secs(10),
+            read_timeout: Duration::from_secs(30),
+            proxy: None,
+            ca_certs: None,
+        }
+    }
+}
+
+/// Create the HTTP client.
+#[instrument(level = "debug")]
+pub fn client(options: &ClientOptions) -> Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder()
+        .user_agent(&options.user_agent)
+        .connect_timeout(options.connect_timeout)
+        .read_timeout(options.read_timeout);
+    if let Some(proxy) = &options.proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+    }
+    if let Some(ca_certs) = &options.ca_certs {
+        builder = builder.tls_certs_merge(reqwest::Certificate::from_pem_bundle(ca_certs)?);
+    }
+    Ok(builder.build()?)
+}
+
+/// Make sure that the API base URL is treated as a directory by
+/// `Url::join`, so that a path prefix like the `/api/v3` of github
+/// enterprise servers is kept.
+pub fn api_base(url: &Url) -> Url {
+    let mut url = url.clone();
+    if !url.path().ends_with('/') {
+        url.set_path(&format!("{}/", url.path()));
+    }
+    url
+}
+
+/// Validators of a response, used to make conditional requests that are
+/// answered with 304 Not Modified if the resource didn't change.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    pub fn from_response(response: &reqwest::Response) -> Validators {
+        Validators {
+            etag: header(response.headers(), ETAG.as_str()).map(String::from),
+            last_modified: header(response.headers(), LAST_MODIFIED.as_str()).map(String::from),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+
+    /// Make the request conditional.
+    pub fn apply(&self, mut request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        if let Some(etag) = &self.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &self.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+        request
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Maximum number of retries of each request.
+    pub retries: u32,
+    /// Delay before the first retry, doubled after each one.
+    pub base_delay: Duration,
+    /// Maximum delay between retries.
+    pub max_delay: Duration,
+    /// Maximum time spent on a request, including all retries.
+    pub deadline: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            retries: 5,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            deadline: Duration::from_secs(300),
+        }
+    }
+}
+
+/// Why a request should be retried.
+#[derive(Debug, PartialEq, Eq)]
+enum Retry {
+    /// Rate limited, with the delay requested by the server, if any.
+    RateLimited(Option<Duration>),
+    /// Server or network error that may go away by itself.
+    Transient,
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+/// Check if a response should be retried.
+///
+/// Github signals rate limits with either 403 or 429, along with
+/// `Retry-After` for secondary rate limits or `X-RateLimit-Remaining: 0`
+/// when the primary one is exhausted; docker hub uses 429.
+fn classify(status: StatusCode, headers: &HeaderMap, now: SystemTime) -> Option<Retry> {
+    let retry_after = header(headers, "retry-after")
+        .and_then(|secs| secs.trim().parse::<u64>().ok())
+        .map(Duration::from_secs);
+    let exhausted = header(headers, "x-ratelimit-remaining") == Some("0");
+    let reset = header(headers, "x-ratelimit-reset")
+        .and_then(|reset| reset.trim().parse::<u64>().ok())
+        .filter(|_| exhausted)
+        .map(|reset| {
+            let now = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+            // The reset time has a 1s granularity
+            Duration::from_secs(reset + 1).saturating_sub(now)
+        });
+    match status {
+        StatusCode::TOO_MANY_REQUESTS => Some(Retry::RateLimited(retry_after.or(reset))),
+        StatusCode::FORBIDDEN if retry_after.is_some() || exhausted => {
+            Some(Retry::RateLimited(retry_after.or(reset)))
+        }
+        StatusCode::INTERNAL_SERVER_ERROR
+        | StatusCode::BAD_GATEWAY
+        | StatusCode::SERVICE_UNAVAILABLE
+        | StatusCode::GATEWAY_TIMEOUT => Some(Retry::Transient),
+        _ => None,
+    }
+}
+
+/// Exponential backoff with full jitter for the given retry.
+fn backoff(policy: &RetryPolicy, retry: u32) -> Duration {
+    let max = policy
+        .base_delay
+        .saturating_mul(2_u32.saturating_pow(retry))
+        .min(policy.max_delay);
+    // A randomly seeded hasher is enough of a random source for jitter
+    let random = RandomState::new().hash_one(retry);
+    max.mul_f64((random % 1000) as f64 / 1000.0)
+}
+
+fn host(url: &Url) -> &str {
+    url.host_str().unwrap_or_default()
+}
+
+/// Send the request, retrying it according to the retry policy of the
+/// configuration.
+///
+/// Responses that are not retried are returned as they are, errors
+/// included; a request that is still rate limited when we give up fails
+/// with [`Error::RateLimited`].
+#[instrument(level = "debug", skip(config, request))]
+pub async fn send(
+    config: &updater::Config,
+    url: &Url,
+    request: reqwest::RequestBuilder,
+) -> Result<reqwest::Response> {
+    if config.offline {
+        return Err(Error::Offline(url.clone()));
+    }
+    let policy = &config.retry;
+    let start = Instant::now();
+    let mut retry = 0;
+    loop {
+        let attempt = request
+            .try_clone()
+            .expect("requests with streaming bodies can't be retried");
+        let sent = Instant::now();
+        let (reason, delay) = match attempt.send().await {
+            Ok(response) => {
+                config
+                    .stats
+                    .request(host(url), sent.elapsed(), response.headers());
+                match classify(response.status(), response.headers(), SystemTime::now()) {
+                    None => return Ok(response),
+                    Some(reason) => {
+                        let delay = match reason {
+                            Retry::RateLimited(Some(delay)) => delay,
+                            _ => backoff(policy, retry),
+                        };
+                        if retry >= policy.retries || start.elapsed() + delay > policy.deadline {
+                            return match reason {
+                                Retry::RateLimited(_) => {
+                                    Err(Error::RateLimited(url.clone(), response.status()))
+                                }
+                                Retry::Transient => Ok(response),
+                            };
+                        }
+                        (response.status().to_string(), delay)
+                    }
+                }
+            }
+            Err(e) if e.is_timeout() || e.is_connect() => {
+                let delay = backoff(policy, retry);
+                if retry >= policy.retries || start.elapsed() + delay > policy.deadline {
+                    return Err(e.into());
+                }
+                (e.to_string(), delay)
+            }
+            Err(e) => return Err(e.into()),
+        };
+        event!(
+            Level::WARN,
+            url = %url,
+            reason,
+            retry,
+            delay = ?delay,
+            "retrying request"
+        );
+        config.stats.retry(host(url));
+        tokio::time::sleep(delay).await;
+        retry += 1;
+    }
+}
+
+/// Send the request made conditional with the validators, returning
+/// `None` if the server answered with 304 Not Modified.
+#[instrument(level = "debug", skip(config, request))]
+pub async fn send_if_modified(
+    config: &updater::Config,
+    url: &Url,
+    request: reqwest::RequestBuilder,
+    validators: &Validators,
+) -> Result<Option<reqwest::Response>> {
+    let response = send(config, url, validators.apply(request)).await?;
+    if response.status() == StatusCode::NOT_MODIFIED && !validators.is_empty() {
+        event!(Level::INFO, url = %url, "not modified");
+        return Ok(None);
+    }
+    Ok(Some(response))
+}
+
+/// Read the body of the response to a request to the URL.
+pub async fn bytes(
+    config: &updater::Config,
+    url: &Url,
+    response: reqwest::Response,
+) -> Result<Vec<u8>> {
+    let data = response.bytes().await?;
+    config.stats.bytes(host(url), data.len() as u64);
+    Ok(data.to_vec())
+}
+
+/// Read the JSON body of the response to a request to the URL.
+pub async fn json(
+    config: &updater::Config,
+    url: &Url,
+    response: reqwest::Response,
+) -> Result<serde_json::Value> {
+    Ok(serde_json::from_slice(
+        &bytes(config, url, response).await?,
+    )?)
+}
+
+#[test]
+fn test_http_classify() {
+    let now = UNIX_EPOCH + Duration::from_secs(1000);
+    let mut headers = HeaderMap::new();
+    assert_eq!(classify(StatusCode::FORBIDDEN, &headers, now), None);
+    assert_eq!(classify(StatusCode::NOT_FOUND, &headers, now), None);
+    assert_eq!(
+        classify(StatusCode::BAD_GATEWAY, &headers, now),
+        Some(Retry::Transient)
+    );
+    assert_eq!(
+        classify(StatusCode::TOO_MANY_REQUESTS, &headers, now),
+        Some(Retry::RateLimited(None))
+    );
+    headers.insert("x-ratelimit-remaining", "0".parse().unwrap());
+    headers.insert("x-ratelimit-reset", "1060".parse().unwrap());
+    assert_eq!(
+        classify(StatusCode::FORBIDDEN, &headers, now),
+        Some(Retry::RateLimited(Some(Duration::from_secs(61))))
+    );
+    headers.insert("retry-after", "30".parse().unwrap());
+    assert_eq!(
+        classify(StatusCode::FORBIDDEN, &headers, now),
+        Some(Retry::RateLimited(Some(Duration::from_secs(30))))
+    );
+    let policy = RetryPolicy::default();
+    assert!(backoff(&policy, 0) <= Duration::from_secs(1));
+    assert!(backoff(&policy, 20) <= policy.max_delay);
+}
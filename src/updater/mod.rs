@@ -2,5 +2,129 @@
 // This file is subject to the terms and conditions defined in
 // file 'LICENSE', which is part of this source code package.
 
+//! Backends that get the versions of resources, behind the [`Updater`]
+//! trait, and the [`Registry`] that selects the backend of each resource.
+
+use async_trait::async_trait;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::error::Result;
+use crate::resource::Resource;
+use crate::stats::Stats;
+use crate::version::Version;
+use crate::version::VersionInfo;
+
+pub mod auth;
 pub mod docker;
+pub mod git;
 pub mod github;
+pub mod github_app;
+pub mod github_graphql;
+pub mod http;
+pub mod plugin;
+pub mod registry;
+
+pub use registry::Registry;
+
+/// Configuration of the updaters.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// HTTP client shared by all requests.
+    pub client: reqwest::Client,
+    /// User-Agent that the client was created with.
+    pub user_agent: String,
+    pub github: github::Config,
+    pub docker: docker::Config,
+    /// How requests are retried on rate limits and transient errors.
+    pub retry: http::RetryPolicy,
+    /// Updaters used for each kind of resource.
+    pub registry: Registry,
+    /// Statistics of the run, shared by all clones of the configuration.
+    pub stats: Arc<Stats>,
+    /// Fail all HTTP requests instead of sending them.
+    pub offline: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            client: http::client(&Default::default()).expect("error creating HTTP client"),
+            user_agent: http::USER_AGENT.into(),
+            github: Default::default(),
+            docker: Default::default(),
+            retry: Default::default(),
+            registry: Default::default(),
+            stats: Default::default(),
+            offline: false,
+        }
+    }
+}
+
+/// Result of a conditional lookup of versions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fetched {
+    /// The versions, along with the validators of the response they came
+    /// from, which are empty if the backend doesn't support them.
+    Modified(Vec<VersionInfo>, http::Validators),
+    /// The versions didn't change since the response with the validators.
+    NotModified,
+}
+
+impl Fetched {
+    pub fn into_versions(self) -> Option<Vec<VersionInfo>> {
+        match self {
+            Fetched::Modified(versions, _) => Some(versions),
+            Fetched::NotModified => None,
+        }
+    }
+}
+
+/// A backend that lists the versions of resources.
+#[async_trait]
+pub trait Updater: fmt::Debug + Send + Sync {
+    /// Name of the backend, for logs.
+    fn name(&self) -> &str;
+
+    /// Whether the versions of its resources can be looked up in batches
+    /// with the github GraphQL API.
+    fn graphql_batchable(&self) -> bool {
+        false
+    }
+
+    /// Get the versions of the resource, along with the object that each
+    /// one resolves to when the upstream provides it.
+    async fn get_versions(&self, config: &Config, resource: &Resource) -> Result<Vec<VersionInfo>>;
+
+    /// Get the versions of the resource unless they didn't change since
+    /// the response with the given validators.
+    ///
+    /// Backends that don't support conditional requests always get the
+    /// versions.
+    async fn get_versions_if_modified(
+        &self,
+        config: &Config,
+        resource: &Resource,
+        _validators: &http::Validators,
+    ) -> Result<Fetched> {
+        Ok(Fetched::Modified(
+            self.get_versions(config, resource).await?,
+            Default::default(),
+        ))
+    }
+
+    /// Get the object that the version resolves to, using the provided
+    /// list of upstream versions.
+    async fn resolve(
+        &self,
+        _config: &Config,
+        _resource: &Resource,
+        versions: &[VersionInfo],
+        version: &Version,
+    ) -> Result<Option<String>> {
+        Ok(versions
+            .iter()
+            .find(|info| &info.version == version)
+            .and_then(|info| info.target.clone()))
+    }
+}
@@ -0,0 +1,236 @@
+// Copyright (C) 2022 Leandro Lisboa Penz <lpenz@lpenz.org>
+// This file is subject to the terms and conditions defined in
+// file 'LICENSE', which is part of this source code package.
+
+//! External backends, implemented by executables that speak a JSON
+//! protocol over stdin/stdout.
+//!
+//! Backends are given by path or by name, in which case we look for a
+//! `github-workflows-update-backend-<name>` executable in `PATH`. For
+//! each resource we run the executable once, writing a description of the
+//! resource to its stdin:
+//!
+//! ```json
+//! {"protocol": 1, "resource": "docker://artifacts.corp/tool", "kind": "docker", "container": "artifacts.corp/tool"}
+//! ```
+//!
+//! Github resources have `owner`, `repo` and, for workflows, `workflow`
+//! fields instead of `container`, and git resources have `url`.
+//!
+//! The executable must then write the versions to stdout, each with an
+//! optional target, and exit with status 0:
+//!
+//! ```json
+//! {"versions": [{"version": "v1.2.0", "target": "sha256:..."}, {"version": "v1.1.0"}]}
+//! ```
+//!
+//! Any other exit status is an error, described by what the executable
+//! wrote to stderr.
+
+use async_trait::async_trait;
+use serde_json::Value;
+use std::path;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tracing::instrument;
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::resource::Resource;
+use crate::updater;
+use crate::updater::Updater;
+use crate::version::Version;
+use crate::version::VersionInfo;
+
+/// Prefix of the names of backend executables.
+pub const PREFIX: &str = "github-workflows-update-backend-";
+
+/// Version of the protocol.
+const PROTOCOL: u64 = 1;
+
+#[derive(Debug, Clone)]
+pub struct Plugin {
+    name: String,
+    path: path::PathBuf,
+}
+
+impl Plugin {
+    pub fn new(name: String, path: path::PathBuf) -> Plugin {
+        Plugin { name, path }
+    }
+
+    /// Find the backend, given either by path or by name.
+    pub fn find(backend: &str) -> Option<Plugin> {
+        if backend.contains(path::MAIN_SEPARATOR) || backend.contains('/') {
+            let path = path::PathBuf::from(backend);
+            return path.is_file().then(|| Plugin::new(backend.into(), path));
+        }
+        let executable = format!("{PREFIX}{backend}");
+        std::env::split_paths(&std::env::var_os("PATH")?)
+            .map(|dir| dir.join(&executable))
+            .find(|path| path.is_file())
+            .map(|path| Plugin::new(backend.into(), path))
+    }
+}
+
+/// Describe the resource to the backend.
+fn describe(resource: &Resource) -> Value {
+    let mut description = serde_json::json!({
+        "protocol": PROTOCOL,
+        "resource": resource.to_string(),
+    });
+    let fields = match resource {
+        Resource::Docker { container } => serde_json::json!({
+            "kind": "docker",
+            "container": container,
+        }),
+        Resource::GhAction { user, repo } => serde_json::json!({
+            "kind": "github-action",
+            "owner": user,
+            "repo": repo,
+        }),
+        Resource::GhWorkflow {
+            user,
+            repo,
+            workflow,
+        } => serde_json::json!({
+            "kind": "github-workflow",
+            "owner": user,
+            "repo": repo,
+            "workflow": workflow,
+        }),
+        Resource::Git { url } => serde_json::json!({
+            "kind": "git",
+            "url": url,
+        }),
+    };
+    if let (Some(description), Value::Object(fields)) = (description.as_object_mut(), fields) {
+        description.extend(fields);
+    }
+    description
+}
+
+fn parse_versions(data: Value) -> Result<Vec<VersionInfo>> {
+    data.get("versions")
+        .and_then(|versions| versions.as_array())
+        .ok_or_else(|| Error::JsonParsing("versions list not found in backend output".into()))?
+        .iter()
+        .map(|entry| {
+            let version_str = entry
+                .get("version")
+                .and_then(|version| version.as_str())
+                .ok_or_else(|| {
+                    Error::JsonParsing("version field not found in backend output".into())
+                })?;
+            let version = Version::new(version_str)
+                .ok_or_else(|| Error::VersionParsing(version_str.into()))?;
+            let target = entry
+                .get("target")
+                .and_then(|target| target.as_str())
+                .map(String::from);
+            Ok(VersionInfo::new(version, target))
+        })
+        .collect()
+}
+
+#[async_trait]
+impl Updater for Plugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[instrument(level = "debug", skip(config))]
+    async fn get_versions(
+        &self,
+        config: &updater::Config,
+        resource: &Resource,
+    ) -> Result<Vec<VersionInfo>> {
+        let failed = |reason: String| Error::Plugin(self.name.clone(), reason);
+        let mut child = tokio::process::Command::new(&self.path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| failed(e.to_string()))?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        match stdin
+            .write_all(describe(resource).to_string().as_bytes())
+            .await
+        {
+            // The backend may exit without reading its input; we report
+            // its exit status instead
+            Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => {
+                return Err(failed(e.to_string()));
+            }
+            _ => {}
+        }
+        drop(stdin);
+        let output = tokio::time::timeout(config.retry.deadline, child.wait_with_output())
+            .await
+            .map_err(|_| failed("timed out".into()))?
+            .map_err(|e| failed(e.to_string()))?;
+        if !output.status.success() {
+            return Err(failed(format!(
+                "{}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        parse_versions(serde_json::from_slice(&output.stdout)?)
+    }
+}
+
+#[cfg(all(test, unix))]
+#[tokio::test]
+async fn test_plugin() -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    let script = dir.join(format!("{PREFIX}artifacts"));
+    std::fs::write(
+        &script,
+        format!(
+            "#!/bin/sh\ncat > {}/input.json\necho '{{\"versions\": [{{\"version\": \"v1.2.0\", \"target\": \"sha256:aaa\"}}, {{\"version\": \"v1.1.0\"}}]}}'\n",
+            dir.display()
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let failing = dir.join("failing");
+    std::fs::write(&failing, "#!/bin/sh\necho 'no such artifact' >&2\nexit 3\n").unwrap();
+    std::fs::set_permissions(&failing, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let mut config = updater::Config::default();
+    config.registry.register_pattern(
+        "docker://artifacts.corp/*",
+        std::sync::Arc::new(Plugin::find(script.to_str().unwrap()).unwrap()),
+    );
+    let tool = Resource::new_docker("artifacts.corp/tool".into());
+    let versions = tool.get_versions(&config).await?;
+    let input = std::fs::read_to_string(dir.join("input.json")).unwrap();
+    let plugin = Plugin::find(failing.to_str().unwrap()).unwrap();
+    let error = plugin.get_versions(&config, &tool).await.unwrap_err();
+    assert_eq!(
+        serde_json::from_str::<Value>(&input)?,
+        serde_json::json!({
+            "protocol": 1,
+            "resource": "docker://artifacts.corp/tool",
+            "kind": "docker",
+            "container": "artifacts.corp/tool",
+        })
+    );
+    assert_eq!(
+        versions,
+        [
+            VersionInfo::new(Version::new("v1.2.0").unwrap(), Some("sha256:aaa".into())),
+            VersionInfo::new(Version::new("v1.1.0").unwrap(), None),
+        ]
+    );
+    assert!(error.to_string().contains("no such artifact"));
+    let debian = Resource::new_docker("debian".into());
+    assert_eq!(
+        config.registry.get(&config, &debian).unwrap().name(),
+        "docker"
+    );
+    Ok(())
+}
@@ -0,0 +1,160 @@
+// Copyright (C) 2022 Leandro Lisboa Penz <lpenz@lpenz.org>
+// This file is subject to the terms and conditions defined in
+// file 'LICENSE', which is part of this source code package.
+
+//! The [`Registry`] maps kinds of resources, optionally restricted to a
+//! host, to the [`Updater`] that serves them.
+//!
+//! Updaters can also be registered for the resources that match a glob
+//! pattern, which take precedence over the ones of kinds and hosts.
+
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::resource::Kind;
+use crate::resource::Resource;
+use crate::updater::Config;
+use crate::updater::Updater;
+use crate::updater::docker::Docker;
+use crate::updater::git::Git;
+use crate::updater::github::Github;
+
+#[derive(Debug, Clone)]
+pub struct Registry {
+    /// Updaters indexed by resource kind and lowercase host, with `None`
+    /// as the host of the fallback updater of the kind.
+    updaters: HashMap<(Kind, Option<String>), Arc<dyn Updater>>,
+    /// Updaters of the resources that match a pattern, in the order they
+    /// were registered.
+    patterns: Vec<(Regex, Arc<dyn Updater>)>,
+}
+
+/// Convert a glob pattern, where `*` matches anything, into a regex.
+fn pattern_regex(pattern: &str) -> Regex {
+    let re = pattern
+        .split('*')
+        .map(regex::escape)
+        .collect::<Vec<_>>()
+        .join(".*");
+    Regex::new(&format!("^{re}$")).expect("escaped pattern is a valid regex")
+}
+
+impl Registry {
+    /// Create a registry without any updater.
+    pub fn empty() -> Registry {
+        Registry {
+            updaters: Default::default(),
+            patterns: Default::default(),
+        }
+    }
+
+    /// Register the updater of all resources of the kind, replacing the
+    /// current one.
+    pub fn register(&mut self, kind: Kind, updater: Arc<dyn Updater>) {
+        self.updaters.insert((kind, None), updater);
+    }
+
+    /// Register the updater of the resources of the kind that are served
+    /// by the given host, taking precedence over the one of the kind.
+    pub fn register_host(&mut self, kind: Kind, host: &str, updater: Arc<dyn Updater>) {
+        self.updaters
+            .insert((kind, Some(host.to_lowercase())), updater);
+    }
+
+    /// Register the updater of the resources whose string matches the
+    /// glob pattern, like `docker://artifacts.corp/*`; the first matching
+    /// pattern wins.
+    pub fn register_pattern(&mut self, pattern: &str, updater: Arc<dyn Updater>) {
+        self.patterns.push((pattern_regex(pattern), updater));
+    }
+
+    /// Get the updater of the resource.
+    pub fn get(&self, config: &Config, resource: &Resource) -> Option<&Arc<dyn Updater>> {
+        let resource_str = resource.to_string();
+        if let Some((_, updater)) = self
+            .patterns
+            .iter()
+            .find(|(pattern, _)| pattern.is_match(&resource_str))
+        {
+            return Some(updater);
+        }
+        let kind = resource.kind();
+        resource
+            .host(config)
+            .and_then(|host| self.updaters.get(&(kind, Some(host))))
+            .or_else(|| self.updaters.get(&(kind, None)))
+    }
+}
+
+impl Default for Registry {
+    /// Registry with the builtin updaters.
+    fn default() -> Self {
+        let mut registry = Registry::empty();
+        let github: Arc<dyn Updater> = Arc::new(Github);
+        registry.register(Kind::Docker, Arc::new(Docker));
+        registry.register(Kind::GhAction, github.clone());
+        registry.register(Kind::GhWorkflow, github);
+        registry.register(Kind::Git, Arc::new(Git));
+        registry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Result;
+    use crate::version::Version;
+    use crate::version::VersionInfo;
+    use async_trait::async_trait;
+    use url::Url;
+
+    #[derive(Debug)]
+    struct Internal;
+
+    #[async_trait]
+    impl Updater for Internal {
+        fn name(&self) -> &str {
+            "internal"
+        }
+
+        async fn get_versions(
+            &self,
+            _config: &Config,
+            _resource: &Resource,
+        ) -> Result<Vec<VersionInfo>> {
+            Ok(vec![VersionInfo::new(
+                Version::new("v1.2.3").unwrap(),
+                Some("abc".into()),
+            )])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_updater_registry() -> Result<()> {
+        let mut config = Config::default();
+        config.github.api_urls.insert(
+            "corp".into(),
+            Url::parse("https://GHES.example.com/api/v3")?,
+        );
+        config
+            .registry
+            .register_host(Kind::GhAction, "ghes.example.com", Arc::new(Internal));
+        let internal = Resource::new_ghaction("corp".into(), "deploy".into());
+        let checkout = Resource::new_ghaction("actions".into(), "checkout".into());
+        let image = Resource::new_docker("debian".into());
+        let name = |resource| config.registry.get(&config, resource).unwrap().name();
+        assert_eq!(name(&internal), "internal");
+        assert_eq!(name(&checkout), "github");
+        assert_eq!(name(&image), "docker");
+        let versions = internal.get_versions(&config).await?;
+        assert_eq!(
+            internal
+                .resolve(&config, &versions, &Version::new("v1.2.3").unwrap())
+                .await?,
+            Some("abc".into())
+        );
+        assert!(Registry::empty().get(&config, &image).is_none());
+        Ok(())
+    }
+}
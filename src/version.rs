@@ -28,6 +28,22 @@ impl Version {
     }
 }
 
+/// A [`Version`] as listed by the upstream repository, along with the
+/// object it currently points to
+#[derive(Debug, PartialEq, Eq, Clone, Default, Hash)]
+pub struct VersionInfo {
+    pub version: Version,
+    /// Digest of the docker image or sha of the git object that the
+    /// version resolves to, when the upstream provides it
+    pub target: Option<String>,
+}
+
+impl VersionInfo {
+    pub fn new(version: Version, target: Option<String>) -> VersionInfo {
+        VersionInfo { version, target }
+    }
+}
+
 impl fmt::Display for Version {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.string)
@@ -11,14 +11,17 @@ use std::collections::HashMap;
 use std::collections::HashSet;
 use std::io;
 use std::path;
+use std::sync::Arc;
 use tokio::io::AsyncReadExt;
-use tokio::io::AsyncWriteExt;
 use tracing::Level;
 use tracing::event;
 use tracing::instrument;
 
+use crate::error::Error;
 use crate::proxy;
+use crate::report::Location;
 use crate::resource::Resource;
+use crate::updater;
 use crate::version::Version;
 
 #[derive(Debug)]
@@ -30,9 +33,16 @@ pub struct Workflow {
     /// Set with all [`Resource`]s that the workflow `uses` along with the
     /// current versions.
     pub uses: HashSet<(Resource, Version)>,
-    /// The latest version of each [`Resource`] as fetched from the
-    /// upstream docker or github repository.
-    pub latest: HashMap<Resource, Version>,
+    /// The version that each resource-version in [`Workflow::uses`]
+    /// should be updated to, as fetched from the upstream docker or github
+    /// repository.
+    pub latest: HashMap<(Resource, Version), Version>,
+    /// The error that prevented us from getting the versions of each
+    /// [`Resource`] that couldn't be looked up.
+    pub unresolved: HashMap<Resource, Arc<Error>>,
+    /// [`Resource`]s that are not supported by their updater, like images
+    /// of docker registries other than docker hub.
+    pub unsupported: HashSet<Resource>,
 }
 
 impl Workflow {
@@ -48,43 +58,318 @@ impl Workflow {
             contents,
             uses,
             latest: Default::default(),
+            unresolved: Default::default(),
+            unsupported: Default::default(),
         })
     }
 
     #[instrument(level = "debug")]
-    pub async fn fetch_latest_versions(&mut self, proxy_server: &proxy::Server) {
+    pub async fn fetch_latest_versions(&mut self, proxy_server: &proxy::Server, pin_latest: bool) {
         let tasks = self
             .uses
             .iter()
             .map(|rv| (rv, proxy_server.new_client()))
             .map(|((resource, current_version), proxy_client)| async move {
-                proxy_client
-                    .fetch_latest_version(resource, current_version)
-                    .await
+                let latest = proxy_client
+                    .fetch_latest_version(resource, current_version, pin_latest)
+                    .await;
+                (resource, current_version, latest)
             });
-        self.latest = join_all(tasks)
-            .await
+        for (resource, current_version, latest) in join_all(tasks).await {
+            match latest {
+                Ok(Some(latest)) => {
+                    self.latest
+                        .insert((resource.clone(), current_version.clone()), latest);
+                }
+                Ok(None) => {}
+                Err(e) if matches!(*e, Error::NotDockerHub(_)) => {
+                    self.unsupported.insert(resource.clone());
+                }
+                Err(e) => {
+                    self.unresolved.insert(resource.clone(), e);
+                }
+            }
+        }
+    }
+
+    /// Make sure that the version we update the resource-version to is at
+    /// least `minimum`, picking the latest version otherwise.
+    #[instrument(level = "debug")]
+    pub async fn enforce_minimum(
+        &mut self,
+        proxy_server: &proxy::Server,
+        resource: &Resource,
+        current_version: &Version,
+        minimum: &Version,
+    ) {
+        let Some(minimum) = &minimum.version else {
+            return;
+        };
+        let rv = (resource.clone(), current_version.clone());
+        if self
+            .latest
+            .get(&rv)
+            .and_then(|latest| latest.version.as_ref())
+            .is_some_and(|latest| latest >= minimum)
+        {
+            return;
+        }
+        let Ok(versions) = proxy_server.new_client().get_versions(resource).await else {
+            return;
+        };
+        let candidate = versions
             .into_iter()
-            .flatten()
-            .collect::<HashMap<_, _>>();
+            .map(|info| info.version)
+            .filter(|version| version.version.as_ref().is_some_and(|v| v >= minimum))
+            .max();
+        if let Some(candidate) = candidate {
+            self.latest.insert(rv, candidate);
+        } else {
+            event!(
+                Level::WARN,
+                resource = %resource,
+                minimum = %minimum,
+                "no version available at or above minimum"
+            );
+        }
+    }
+
+    /// The github resources used with a floating tag (`v3`, `v3.1`).
+    pub fn floating_tags(&self) -> impl Iterator<Item = &(Resource, Version)> {
+        self.uses.iter().filter(|(resource, version)| {
+            resource.is_github() && updater::github::floating_components(version).is_some()
+        })
     }
 
+    /// The github resources pinned to a commit sha.
+    pub fn sha_pins(&self) -> impl Iterator<Item = &(Resource, Version)> {
+        self.uses
+            .iter()
+            .filter(|(resource, version)| resource.is_github() && updater::github::is_sha(version))
+    }
+
+    /// The github resources used with a version that is neither semver
+    /// nor a commit sha, which may be a branch.
+    pub fn named_versions(&self) -> impl Iterator<Item = &(Resource, Version)> {
+        self.uses.iter().filter(|(resource, version)| {
+            resource.is_github() && version.version.is_none() && !updater::github::is_sha(version)
+        })
+    }
+
+    /// Check that the floating tags (`v3`, `v3.1`) used by the workflow
+    /// point to the newest matching full release.
+    ///
+    /// Returns the stale resource-versions along with the release they
+    /// should be pointing to.
     #[instrument(level = "debug")]
-    pub async fn update_file(&self) -> Result<bool> {
-        let mut contents = self.contents.clone();
-        for (resource, current_version) in &self.uses {
-            if let Some(latest_version) = self.latest.get(resource) {
-                let current_line = resource.versioned_string(current_version);
+    pub async fn check_floating(
+        &self,
+        proxy_server: &proxy::Server,
+    ) -> Vec<(Resource, Version, Version)> {
+        let tasks = self
+            .floating_tags()
+            .map(|rv| (rv, proxy_server.new_client()))
+            .map(|((resource, floating), proxy_client)| async move {
+                let versions = proxy_client.get_versions(resource).await.ok()?;
+                let repo_url = resource.repo_url(proxy_server.config()).ok()?;
+                match updater::github::stale_floating(
+                    proxy_server.config(),
+                    &repo_url,
+                    &versions,
+                    floating,
+                )
+                .await
+                {
+                    Ok(newest) => newest.map(|newest| (resource.clone(), floating.clone(), newest)),
+                    Err(e) => {
+                        event!(
+                            Level::ERROR,
+                            resource = %resource,
+                            version = %floating,
+                            error = %e,
+                            "error checking floating tag"
+                        );
+                        None
+                    }
+                }
+            });
+        join_all(tasks).await.into_iter().flatten().collect()
+    }
+
+    /// Check that the commits of SHA-pinned github resources are reachable
+    /// from one of the upstream repository's branches or tags.
+    ///
+    /// Returns the resource-versions with unreachable commits.
+    #[instrument(level = "debug")]
+    pub async fn check_impostors(&self, proxy_server: &proxy::Server) -> Vec<(Resource, Version)> {
+        let tasks = self
+            .sha_pins()
+            .map(|rv| (rv, proxy_server.new_client()))
+            .map(|((resource, version), proxy_client)| async move {
+                let versions = proxy_client.get_versions(resource).await.ok()?;
+                let repo_url = resource.repo_url(proxy_server.config()).ok()?;
+                match updater::github::is_reachable(
+                    proxy_server.config(),
+                    &repo_url,
+                    &versions,
+                    &version.string,
+                )
+                .await
+                {
+                    Ok(Some(true)) => None,
+                    Ok(Some(false)) => Some((resource.clone(), version.clone())),
+                    Ok(None) => {
+                        event!(
+                            Level::WARN,
+                            resource = %resource,
+                            version = %version,
+                            "unable to tell if the commit is reachable"
+                        );
+                        None
+                    }
+                    Err(e) => {
+                        event!(
+                            Level::ERROR,
+                            resource = %resource,
+                            version = %version,
+                            error = %e,
+                            "error checking commit reachability"
+                        );
+                        None
+                    }
+                }
+            });
+        join_all(tasks).await.into_iter().flatten().collect()
+    }
+
+    /// Find the github resources pinned to a branch: versions that are
+    /// neither semver nor a commit sha, that are not upstream tags, and
+    /// that upstream has a branch with.
+    #[instrument(level = "debug")]
+    pub async fn check_branch_pins(
+        &self,
+        proxy_server: &proxy::Server,
+    ) -> Vec<(Resource, Version)> {
+        let tasks = self
+            .named_versions()
+            .map(|rv| (rv, proxy_server.new_client()))
+            .map(|((resource, version), proxy_client)| async move {
+                let versions = proxy_client.get_versions(resource).await.ok()?;
+                if versions.iter().any(|info| &info.version == version) {
+                    return None;
+                }
+                let repo_url = resource.repo_url(proxy_server.config()).ok()?;
+                match updater::github::is_branch(proxy_server.config(), &repo_url, &version.string)
+                    .await
+                {
+                    Ok(true) => Some((resource.clone(), version.clone())),
+                    Ok(false) => None,
+                    Err(e) => {
+                        event!(
+                            Level::ERROR,
+                            resource = %resource,
+                            version = %version,
+                            error = %e,
+                            "error checking if the version is a branch"
+                        );
+                        None
+                    }
+                }
+            });
+        join_all(tasks).await.into_iter().flatten().collect()
+    }
+
+    /// Resolve the commit sha or image digest of each of the provided
+    /// resource-versions.
+    #[instrument(level = "debug", skip(resource_versions))]
+    pub async fn resolve_targets(
+        &self,
+        proxy_server: &proxy::Server,
+        resource_versions: impl Iterator<Item = &(Resource, Version)>,
+    ) -> HashMap<(Resource, Version), String> {
+        let tasks = resource_versions
+            .map(|rv| (rv, proxy_server.new_client()))
+            .map(|((resource, version), proxy_client)| async move {
+                let versions = proxy_client.get_versions(resource).await.ok()?;
+                match resource
+                    .resolve(proxy_server.config(), &versions, version)
+                    .await
+                {
+                    Ok(target) => {
+                        target.map(|target| ((resource.clone(), version.clone()), target))
+                    }
+                    Err(e) => {
+                        event!(
+                            Level::ERROR,
+                            resource = %resource,
+                            version = %version,
+                            error = %e,
+                            "error resolving version"
+                        );
+                        None
+                    }
+                }
+            });
+        join_all(tasks).await.into_iter().flatten().collect()
+    }
+
+    /// Where the resource-version is used in the workflow.
+    pub fn location(&self, resource: &Resource, version: &Version) -> Option<Location> {
+        let range = resource
+            .occurrences(&self.contents, version)
+            .into_iter()
+            .next()?;
+        Some(Location::at(&self.contents, range.start))
+    }
+
+    /// Contents of the workflow with the resources updated to their latest
+    /// versions, along with the resource-versions that were replaced.
+    pub fn updated_contents(&self) -> (String, HashSet<(Resource, Version)>) {
+        let mut replacements = self
+            .uses
+            .iter()
+            .filter_map(|(resource, current_version)| {
+                let latest_version = self
+                    .latest
+                    .get(&(resource.clone(), current_version.clone()))?;
                 let latest_line = resource.versioned_string(latest_version);
-                contents = contents.replace(&current_line, &latest_line);
+                Some(
+                    resource
+                        .occurrences(&self.contents, current_version)
+                        .into_iter()
+                        .map(move |range| {
+                            (range, latest_line.clone(), (resource, current_version))
+                        }),
+                )
+            })
+            .flatten()
+            .collect::<Vec<_>>();
+        replacements.sort_by_key(|(range, _, _)| range.start);
+        let mut contents = String::new();
+        let mut replaced = HashSet::new();
+        let mut end = 0;
+        for (range, latest_line, (resource, current_version)) in replacements {
+            if range.start < end {
+                continue;
             }
+            contents.push_str(&self.contents[end..range.start]);
+            contents.push_str(&latest_line);
+            end = range.end;
+            replaced.insert((resource.clone(), current_version.clone()));
         }
-        let updated = contents != self.contents;
-        if updated {
-            let mut file = tokio::fs::File::create(&self.filename).await?;
-            file.write_all(contents.as_bytes()).await?;
+        contents.push_str(&self.contents[end..]);
+        (contents, replaced)
+    }
+
+    /// Write the updated contents to the file, returning the
+    /// resource-versions that were replaced.
+    #[instrument(level = "debug")]
+    pub async fn update_file(&self) -> Result<HashSet<(Resource, Version)>> {
+        let (contents, replaced) = self.updated_contents();
+        if contents != self.contents {
+            tokio::fs::write(&self.filename, contents).await?;
         }
-        Ok(updated)
+        Ok(replaced)
     }
 }
 
@@ -118,6 +403,30 @@ fn buf_parse(r: impl io::BufRead) -> Result<HashSet<(Resource, Version)>> {
                 );
             }
         }
+        if let Some(container) = job.get(Value::String("container".into())) {
+            let image = match container.get(Value::String("image".into())) {
+                Some(image) => image,
+                None => container,
+            };
+            let reference = image
+                .as_str()
+                .ok_or_else(|| eyre!("invalid type for container entry"))?;
+            if let Ok((resource, version)) = Resource::parse_image(reference) {
+                event!(
+                    Level::INFO,
+                    resource = %resource,
+                    version = %version,
+                    "parsed entity"
+                );
+                ret.insert((resource, version));
+            } else {
+                event!(
+                    Level::WARN,
+                    reference = reference,
+                    "unable to parse container image"
+                );
+            }
+        }
         if let Some(steps) = job.get(Value::String("steps".into())) {
             let steps = steps
                 .as_sequence()
@@ -163,7 +472,116 @@ jobs:
       - run: ls
   rust:
     uses: lpenz/ghworkflow-rust/.github/workflows/rust.yml@v0.4
+  container:
+    runs-on: ubuntu-latest
+    container: lpenz/debian-bookworm-amd64:latest
+    steps:
+      - run: ls
+";
+    let uses = buf_parse(s.as_bytes())?;
+    assert_eq!(uses.len(), 4);
+    assert!(uses.contains(&(
+        Resource::new_docker("lpenz/debian-bookworm-amd64".into()),
+        Version::new("latest").unwrap()
+    )));
+    Ok(())
+}
+
+#[test]
+fn test_updated_contents() -> Result<()> {
+    let contents = "\
+jobs:
+  build:
+    runs-on: ubuntu-latest
+    container: mynode:18
+    steps:
+      - uses: docker://debian:12
+      - uses: docker://debian:1
+      - uses: docker://node:18
+";
+    let mut workflow = Workflow {
+        filename: "ci.yml".into(),
+        contents: contents.into(),
+        uses: buf_parse(contents.as_bytes())?,
+        latest: Default::default(),
+        unresolved: Default::default(),
+        unsupported: Default::default(),
+    };
+    let v = |s: &str| Version::new(s).unwrap();
+    let debian = Resource::new_docker("debian".into());
+    workflow.latest.insert((debian.clone(), v("12")), v("13"));
+    workflow.latest.insert((debian, v("1")), v("13"));
+    workflow
+        .latest
+        .insert((Resource::new_docker("node".into()), v("18")), v("22"));
+    let (updated, replaced) = workflow.updated_contents();
+    assert_eq!(
+        updated,
+        contents
+            .replace("debian:1\n", "debian:13\n")
+            .replace("debian:12", "debian:13")
+            .replace("//node:18", "//node:22")
+    );
+    // mynode:18 is not node:18, and has no newer version
+    assert_eq!(replaced.len(), 3);
+    assert!(replaced.contains(&(Resource::new_docker("node".into()), v("18"))));
+    assert!(!replaced.contains(&(Resource::new_docker("mynode".into()), v("18"))));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_fetch_latest_versions_pin_latest() -> Result<()> {
+    use crate::snapshot::Snapshot;
+    use crate::version::VersionInfo;
+    let contents = "\
+jobs:
+  build:
+    runs-on: ubuntu-latest
+    container: debian:latest
+    steps:
+      - uses: docker://debian:11
 ";
-    buf_parse(s.as_bytes())?;
+    let debian = Resource::new_docker("debian".into());
+    let v = |s: &str| Version::new(s).unwrap();
+    let info = |s: &str, digest: &str| VersionInfo::new(v(s), Some(digest.into()));
+    let mut snapshot = Snapshot::default();
+    snapshot.insert(
+        &debian,
+        "docker",
+        vec![
+            info("latest", "sha256:bbb"),
+            info("11", "sha256:aaa"),
+            info("12", "sha256:bbb"),
+            info("13", "sha256:ccc"),
+        ],
+    );
+    let mut config = updater::Config::default();
+    config
+        .registry
+        .register_pattern("docker://*", Arc::new(snapshot));
+    let proxy_server = proxy::Server::new(config, Default::default());
+    let mut workflow = Workflow {
+        filename: "ci.yml".into(),
+        contents: contents.into(),
+        uses: buf_parse(contents.as_bytes())?,
+        latest: Default::default(),
+        unresolved: Default::default(),
+        unsupported: Default::default(),
+    };
+    workflow.fetch_latest_versions(&proxy_server, true).await;
+    // latest is pinned to the tag with the same digest, while 11 goes to
+    // the newest tag
+    assert_eq!(
+        workflow.latest.get(&(debian.clone(), v("latest"))),
+        Some(&v("12"))
+    );
+    assert_eq!(workflow.latest.get(&(debian, v("11"))), Some(&v("13")));
+    let (updated, _) = workflow.updated_contents();
+    assert_eq!(
+        updated,
+        contents
+            .replace("debian:latest", "debian:12")
+            .replace("debian:11", "debian:13")
+    );
     Ok(())
 }
@@ -0,0 +1,246 @@
+// Copyright (C) 2022 Leandro Lisboa Penz <lpenz@lpenz.org>
+// This file is subject to the terms and conditions defined in
+// file 'LICENSE', which is part of this source code package.
+
+//! End-to-end tests against an in-process mock of the github and docker
+//! hub APIs, which serves the recorded responses under `tests/mock` by
+//! request path.
+//!
+//! Each directory under `tests/fixtures` is a repository with:
+//! - `workflows/`: the workflows, copied to `.github/workflows`;
+//! - `args`: optional command line arguments;
+//! - `snapshot.json`: optional snapshot, copied to the repository;
+//! - `expected/`: the golden `stdout`, exit `status` and rewritten
+//!   `workflows/`.
+//!
+//! Run with `UPDATE_GOLDEN=1` to write the golden files from the current
+//! output.
+
+use std::path::Path;
+use std::path::PathBuf;
+use tempfile::TempDir;
+use url::Url;
+
+use github_workflows_update::advisory;
+use github_workflows_update::processor;
+use github_workflows_update::proxy;
+use github_workflows_update::report::Reason;
+use github_workflows_update::updater;
+use github_workflows_update::version::Version;
+
+mod support;
+
+use support::Response;
+
+fn testdir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests")
+}
+
+/// Start the mock server, serving `tests/mock/<path>.json`.
+async fn mock_server() -> Url {
+    let mock = support::serve(|request| {
+        let filename = testdir()
+            .join("mock")
+            .join(format!("{}.json", request.path.trim_start_matches('/')));
+        match std::fs::read(&filename) {
+            Ok(body) => Response::json("200 OK", body),
+            Err(_) => Response::not_found(),
+        }
+    })
+    .await;
+    mock.url
+}
+
+/// Create a scratch repository with the given workflows, removed when
+/// dropped.
+fn scratch_repo(workflows: &Path) -> TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    let target = dir.path().join(".github/workflows");
+    std::fs::create_dir_all(&target).unwrap();
+    for entry in std::fs::read_dir(workflows).unwrap() {
+        let path = entry.unwrap().path();
+        std::fs::copy(&path, target.join(path.file_name().unwrap())).unwrap();
+    }
+    dir
+}
+
+/// Compare the output with the golden file, or write it with
+/// `UPDATE_GOLDEN`.
+fn compare(golden: &Path, actual: &str) {
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(golden.parent().unwrap()).unwrap();
+        std::fs::write(golden, actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(golden)
+        .unwrap_or_else(|e| panic!("error reading {}: {e}", golden.display()));
+    assert_eq!(
+        actual,
+        expected,
+        "{} differs, run with UPDATE_GOLDEN=1 to update it",
+        golden.display()
+    );
+}
+
+/// Run the command on the fixture repository and compare the results
+/// with the golden files.
+async fn golden(name: &str) {
+    let mock = mock_server().await;
+    let fixture = testdir().join("fixtures").join(name);
+    let repo = scratch_repo(&fixture.join("workflows"));
+    let dir = repo.path();
+    let snapshot = fixture.join("snapshot.json");
+    if snapshot.exists() {
+        std::fs::copy(&snapshot, dir.join("snapshot.json")).unwrap();
+    }
+    let args = std::fs::read_to_string(fixture.join("args")).unwrap_or_default();
+    let output = tokio::process::Command::new(env!("CARGO_BIN_EXE_github-workflows-update"))
+        .args(args.split_whitespace())
+        .arg("--docker-hub-url")
+        .arg(mock.as_str())
+        .current_dir(dir)
+        // Keep tokens and configuration of the user out of the test
+        .env_clear()
+        .env("HOME", dir)
+        .env("GITHUB_API_URL", mock.as_str())
+        .output()
+        .await
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout).replace(mock.as_str(), "http://mock/");
+    let expected = fixture.join("expected");
+    compare(&expected.join("stdout"), &stdout);
+    compare(
+        &expected.join("status"),
+        &format!("{}\n", output.status.code().unwrap_or(-1)),
+    );
+    let workflows = dir.join(".github/workflows");
+    for entry in std::fs::read_dir(&workflows).unwrap() {
+        let path = entry.unwrap().path();
+        compare(
+            &expected.join("workflows").join(path.file_name().unwrap()),
+            &std::fs::read_to_string(&path).unwrap(),
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_e2e_update() {
+    golden("update").await;
+}
+
+#[tokio::test]
+async fn test_e2e_dryrun() {
+    golden("dryrun").await;
+}
+
+#[tokio::test]
+async fn test_e2e_unresolved() {
+    golden("unresolved").await;
+}
+
+#[tokio::test]
+async fn test_e2e_offline() {
+    golden("offline").await;
+}
+
+#[tokio::test]
+async fn test_e2e_process_file() {
+    let mock = mock_server().await;
+    let fixture = testdir().join("fixtures/update");
+    let repo = scratch_repo(&fixture.join("workflows"));
+    let mut config = updater::Config::default();
+    config.github.api_url = mock.clone();
+    config.docker.api_url = mock;
+    let server = proxy::Server::new(config, Default::default());
+    let filename = repo.path().join(".github/workflows/ci.yml");
+    let report = processor::process_file(
+        &Default::default(),
+        &Default::default(),
+        None,
+        &server,
+        &filename,
+    )
+    .await
+    .unwrap();
+    assert!(report.any_outdated());
+    assert!(!report.any_unresolved());
+    assert!(report.errors.is_empty());
+    // Only the references that are updated are applied
+    assert!(report.references.iter().any(|reference| reference.applied));
+    assert!(report.references.iter().all(|reference| {
+        reference.applied
+            == reference
+                .candidate
+                .as_ref()
+                .is_some_and(|candidate| candidate != &reference.current)
+    }));
+    // Images of other registries are skipped instead of unresolved
+    assert!(report.findings.iter().any(|finding| {
+        finding.resource.to_string() == "docker://ghcr.io/lpenz/omnilint"
+            && matches!(finding.reason, Reason::Skipped { .. })
+    }));
+    let updated = std::fs::read_to_string(&filename).unwrap();
+    compare(&fixture.join("expected/workflows/ci.yml"), &updated);
+}
+
+/// Write an advisory for the `GitHub Actions` package, fixed at `fixed`.
+fn write_advisory(dir: &Path, id: &str, name: &str, fixed: &str) {
+    let advisory = serde_json::json!({
+        "id": id,
+        "summary": format!("{name} before {fixed}"),
+        "affected": [{
+            "package": { "ecosystem": "GitHub Actions", "name": name },
+            "ranges": [{
+                "type": "ECOSYSTEM",
+                "events": [{ "introduced": "0" }, { "fixed": fixed }],
+            }],
+        }],
+    });
+    std::fs::write(dir.join(format!("{id}.json")), advisory.to_string()).unwrap();
+}
+
+#[tokio::test]
+async fn test_e2e_process_file_advisories() {
+    let mock = mock_server().await;
+    let fixture = testdir().join("fixtures/update");
+    let repo = scratch_repo(&fixture.join("workflows"));
+    let advisories_dir = tempfile::tempdir().unwrap();
+    write_advisory(advisories_dir.path(), "GHSA-1", "actions/checkout", "3.6.0");
+    write_advisory(
+        advisories_dir.path(),
+        "GHSA-2",
+        "lpenz/ghworkflow-rust",
+        "99.0.0",
+    );
+    let advisories = advisory::Database::load(advisories_dir.path())
+        .await
+        .unwrap();
+    let mut config = updater::Config::default();
+    config.github.api_url = mock.clone();
+    config.docker.api_url = mock;
+    let server = proxy::Server::new(config, Default::default());
+    let filename = repo.path().join(".github/workflows/ci.yml");
+    let options = processor::Options {
+        dryrun: true,
+        ..Default::default()
+    };
+    let report = processor::process_file(&options, &advisories, None, &server, &filename)
+        .await
+        .unwrap();
+    let proposed = |id: &str| {
+        report
+            .findings
+            .iter()
+            .find(|finding| {
+                matches!(&finding.reason, Reason::Vulnerable(vulnerability) if vulnerability.id == id)
+            })
+            .unwrap()
+            .proposed
+            .clone()
+    };
+    // The upstream tag that has the fix is proposed, not the advisory
+    // version
+    assert_eq!(proposed("GHSA-1"), Version::new("v4.1.1"));
+    // No upstream tag has the fix
+    assert_eq!(proposed("GHSA-2"), None);
+}
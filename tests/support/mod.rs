@@ -0,0 +1,169 @@
+// Copyright (C) 2022 Leandro Lisboa Penz <lpenz@lpenz.org>
+// This file is subject to the terms and conditions defined in
+// file 'LICENSE', which is part of this source code package.
+
+//! Helpers shared by the unit tests and the end-to-end tests.
+//!
+//! [`serve`] starts an in-process HTTP server that answers each request
+//! with a handler, standing in for github, docker hub and git servers.
+
+#![allow(dead_code)]
+
+use std::sync::Arc;
+use std::sync::Mutex;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+use url::Url;
+
+/// A request received by the mock server.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    /// Path of the request, without the query.
+    pub path: String,
+    pub query: String,
+    /// Request line and headers.
+    pub head: String,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Value of the header, with a case-insensitive name.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.head.lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            key.eq_ignore_ascii_case(name).then(|| value.trim())
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Response {
+    /// Status code and reason, like `200 OK`.
+    pub status: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn new(status: &str, body: impl Into<Vec<u8>>) -> Response {
+        Response {
+            status: status.into(),
+            headers: vec![],
+            body: body.into(),
+        }
+    }
+
+    /// JSON response with the given status.
+    pub fn json(status: &str, body: impl Into<Vec<u8>>) -> Response {
+        Response::new(status, body).header("Content-Type", "application/json")
+    }
+
+    pub fn not_found() -> Response {
+        Response::json("404 Not Found", r#"{"message": "Not Found"}"#)
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Response {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+}
+
+type Handler = dyn Fn(&Request) -> Response + Send + Sync;
+
+/// A running mock server.
+#[derive(Debug, Clone)]
+pub struct Mock {
+    /// Base URL of the server, ending with `/`.
+    pub url: Url,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl Mock {
+    /// Requests received so far.
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+async fn read_request(stream: &mut TcpStream) -> Option<Request> {
+    let mut data = vec![];
+    let mut buf = [0; 4096];
+    let split = loop {
+        if let Some(split) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+            break split;
+        }
+        match stream.read(&mut buf).await {
+            Ok(0) | Err(_) => return None,
+            Ok(len) => data.extend_from_slice(&buf[..len]),
+        }
+    };
+    let head = String::from_utf8_lossy(&data[..split]).to_string();
+    let mut body = data[split + 4..].to_vec();
+    let mut request_line = head.lines().next().unwrap_or_default().split(' ');
+    let method = request_line.next().unwrap_or_default().to_string();
+    let target = request_line.next().unwrap_or("/");
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let mut request = Request {
+        method,
+        path: path.into(),
+        query: query.into(),
+        head,
+        body: vec![],
+    };
+    let length = request
+        .header("content-length")
+        .and_then(|length| length.parse().ok())
+        .unwrap_or(0);
+    while body.len() < length {
+        match stream.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(len) => body.extend_from_slice(&buf[..len]),
+        }
+    }
+    request.body = body;
+    Some(request)
+}
+
+async fn respond(mut stream: TcpStream, handler: Arc<Handler>, requests: Arc<Mutex<Vec<Request>>>) {
+    let Some(request) = read_request(&mut stream).await else {
+        return;
+    };
+    requests.lock().unwrap().push(request.clone());
+    // Handlers may run external programs
+    let Ok(response) = tokio::task::spawn_blocking(move || handler(&request)).await else {
+        return;
+    };
+    let mut head = format!("HTTP/1.1 {}\r\n", response.status);
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str(&format!(
+        "Content-Length: {}\r\nConnection: close\r\n\r\n",
+        response.body.len()
+    ));
+    if stream.write_all(head.as_bytes()).await.is_ok() {
+        let _ = stream.write_all(&response.body).await;
+    }
+}
+
+/// Start a mock server that answers the requests with the handler, until
+/// the runtime is shut down.
+pub async fn serve(handler: impl Fn(&Request) -> Response + Send + Sync + 'static) -> Mock {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+    let handler: Arc<Handler> = Arc::new(handler);
+    let requests = Arc::new(Mutex::new(vec![]));
+    let mock = Mock {
+        url,
+        requests: requests.clone(),
+    };
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(respond(stream, handler.clone(), requests.clone()));
+        }
+    });
+    mock
+}